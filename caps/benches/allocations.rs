@@ -0,0 +1,69 @@
+//! Compares the time taken and allocations made by `parse_capabilities` with
+//! those of `CapabilitiesRef`.
+//!
+//! ```text
+//! cargo bench -p mccs-caps --bench allocations
+//! ```
+
+use {
+    mccs_caps::{parse_capabilities, CapabilitiesRef},
+    std::{
+        alloc::{GlobalAlloc, Layout, System},
+        hint::black_box,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Instant,
+    },
+};
+
+const CAPABILITY_STRING: &str = "(prot(monitor)type(lcd)model(S2721QS)cmds(01 02 03 07 0C E3 F3)vcp(02 04 05 08 10 12 \
+                                 14(05 08 0B 0C) 16 18 1A 52 60( 0F 11 12) 62 AC AE B2 B6 C6 C8 C9 CC(02 03 04 06 09 \
+                                 0A 0D 0E) D6(01 04 05) DC(00 03 05) DF E0 E1 E2(00 1D 02 20 21 0E 12 14 23 24 27) F0(00 \
+                                 08) F1 F2 FD)mccs_ver(2.1)mswhql(1))";
+const ITERATIONS: usize = 10000;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn bench<O, F: FnMut() -> O>(name: &str, mut f: F) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let elapsed = start.elapsed() / ITERATIONS as u32;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / ITERATIONS;
+    println!("{name:<40} {elapsed:>10.2?}/iter {allocations:>5} allocations/iter");
+}
+
+fn main() {
+    bench("parse_capabilities", || {
+        parse_capabilities(black_box(CAPABILITY_STRING)).unwrap()
+    });
+    bench("CapabilitiesRef::parse", || {
+        CapabilitiesRef::parse(black_box(CAPABILITY_STRING)).unwrap()
+    });
+    bench("CapabilitiesRef::parse + vcp_features", || {
+        let caps = CapabilitiesRef::parse(black_box(CAPABILITY_STRING)).unwrap();
+        caps.vcp_features().map(|vcp| vcp.values().count()).sum::<usize>()
+    });
+    bench("CapabilitiesRef::parse + to_capabilities", || {
+        CapabilitiesRef::parse(black_box(CAPABILITY_STRING))
+            .unwrap()
+            .to_capabilities()
+    });
+}
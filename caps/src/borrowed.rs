@@ -0,0 +1,274 @@
+use {
+    super::{
+        capabilities_from_entries,
+        caps::{hexvalue, vcp_ref, vcp_value_ref, vcpname},
+        fold_all,
+        span::Origin,
+        Cap, Charset, IResult, ParseError, ParseOptions, Repeats, Text, Value, ValueParser, Vcp, VcpName, VcpValue,
+    },
+    mccs::{Capabilities, Command, FirmwareVersion, WindowCapability},
+    nom::{character::complete::space0, sequence::preceded, Finish},
+    std::{convert::Infallible, iter},
+};
+
+/// Display capabilities that borrow from the capability string they were
+/// parsed from.
+///
+/// The `cmds`, `vcp` and `vcpname` entries are validated while parsing, but
+/// only decoded as they are iterated over.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CapabilitiesRef<'i> {
+    /// The protocol class, such as `monitor`.
+    pub protocol: Option<Text<'i>>,
+    /// The display type, such as `lcd`.
+    pub ty: Option<Text<'i>>,
+    /// The model name.
+    pub model: Option<Text<'i>>,
+    /// The Microsoft WHQL level.
+    pub ms_whql: Option<u8>,
+    /// The MCCS version, as major and minor.
+    pub mccs_version: Option<(u8, u8)>,
+    /// The EDID block, if reported in the capability string.
+    pub edid: Option<&'i [u8]>,
+    /// The VDIF blocks reported in the capability string.
+    pub vdif: Vec<&'i [u8]>,
+    /// The `windowN()` entries.
+    pub windows: Vec<WindowCapability>,
+    /// The size of the asset tag EEPROM, in bytes.
+    pub asset_eeprom_size: Option<u16>,
+    /// The vendor firmware versions.
+    pub firmware: Vec<FirmwareVersion>,
+    /// Entries that were not recognized.
+    pub unknown_tags: Vec<Value<'i>>,
+    commands: Option<&'i [u8]>,
+    vcp: Vec<&'i [u8]>,
+    vcp_names: Vec<&'i [u8]>,
+    charset: Charset,
+}
+
+impl<'i> CapabilitiesRef<'i> {
+    /// Parses a MCCS capability string without copying it.
+    pub fn parse<C: AsRef<[u8]> + ?Sized>(capability_string: &'i C) -> Result<Self, ParseError> {
+        Self::parse_with(capability_string, &Default::default())
+    }
+
+    /// Parses a MCCS capability string following the given options, without
+    /// copying it.
+    pub fn parse_with<C: AsRef<[u8]> + ?Sized>(
+        capability_string: &'i C,
+        options: &ParseOptions,
+    ) -> Result<Self, ParseError> {
+        let entries = ValueParser::with_options(capability_string.as_ref(), options.clone());
+        let parser = entries.clone();
+        let validate = |tag, value, result: IResult<'i, ()>| {
+            result
+                .finish()
+                .map(|_| value)
+                .map_err(|e| parser.error(e.with_tag(tag)))
+        };
+
+        let mut caps = CapabilitiesRef {
+            charset: options.charset,
+            ..Default::default()
+        };
+        let mut repeats = Repeats::default();
+        for value in entries.nom_iter() {
+            let value = value.map_err(|e| parser.error(e))?;
+            match (options.known_tag(value.tag()), value) {
+                (Some(tag @ "cmds"), Value::String { value, .. }) =>
+                    caps.commands = Some(validate(tag, value, fold_all(hexvalue, || (), |(), _| ())(value))?),
+                (Some(tag @ "vcp"), Value::String { value, .. }) =>
+                    caps.vcp
+                        .push(validate(tag, value, fold_all(vcp_ref, || (), |(), _| ())(value))?),
+                (Some(tag @ "vcpname"), Value::String { value, .. }) => caps.vcp_names.push(validate(
+                    tag,
+                    value,
+                    fold_all(vcpname(Origin::new(value, 0), options.charset), || (), |(), _| ())(value),
+                )?),
+                (_, value) => match repeats.entry(
+                    Cap::parse_entry_with(value, options).map_err(|e| parser.error(e))?,
+                    value,
+                ) {
+                    Cap::Protocol(protocol) => caps.protocol = Some(protocol),
+                    Cap::Type(ty) => caps.ty = Some(ty),
+                    Cap::Model(model) => caps.model = Some(model),
+                    Cap::Whql(whql) => caps.ms_whql = Some(whql),
+                    Cap::MccsVersion(major, minor) => caps.mccs_version = Some((major, minor)),
+                    Cap::Edid(edid) => caps.edid = Some(edid),
+                    Cap::Vdif(vdif) => caps.vdif.push(vdif),
+                    Cap::Window(window) => caps.windows.push(window),
+                    Cap::AssetEeprom(size) => caps.asset_eeprom_size = Some(size),
+                    Cap::Firmware(version) => caps.firmware.push(version),
+                    Cap::Unknown(value) => caps.unknown_tags.push(value),
+                    Cap::Commands(..) | Cap::Vcp(..) | Cap::VcpNames(..) =>
+                        unreachable!("string entries are handled above"),
+                },
+            }
+        }
+
+        Ok(caps)
+    }
+
+    /// The supported DDC/CI commands.
+    pub fn commands(&self) -> impl Iterator<Item = Command> + 'i {
+        items(self.commands.unwrap_or_default(), hexvalue).map(Command::from)
+    }
+
+    /// The supported VCP features, in the order they appear.
+    pub fn vcp_features(&self) -> impl Iterator<Item = VcpRef<'i>> + '_ {
+        self.vcp
+            .iter()
+            .flat_map(|&vcp| items(vcp, vcp_ref))
+            .map(|(feature, values)| VcpRef { feature, values })
+    }
+
+    /// The names of VCP features and their values.
+    pub fn vcp_names(&self) -> impl Iterator<Item = VcpName<'i>> + '_ {
+        self.vcp_names
+            .iter()
+            .flat_map(|&names| items(names, vcpname(Origin::new(names, 0), self.charset)))
+            .map(|(name, _)| name)
+    }
+
+    /// Copies the capabilities into an owned `Capabilities`.
+    ///
+    /// The result is identical to parsing the capability string with
+    /// [`parse_capabilities_with`](crate::parse_capabilities_with).
+    pub fn to_capabilities(&self) -> Capabilities {
+        let vcp = self.vcp.iter().map(|&vcp| {
+            Cap::Vcp(
+                items(vcp, vcp_ref)
+                    .map(|(feature, values)| VcpRef { feature, values }.into())
+                    .collect(),
+            )
+        });
+        let entries = self
+            .protocol
+            .clone()
+            .map(Cap::Protocol)
+            .into_iter()
+            .chain(self.ty.clone().map(Cap::Type))
+            .chain(self.model.clone().map(Cap::Model))
+            .chain(
+                self.commands
+                    .map(|_| Cap::Commands(self.commands().map(Into::into).collect())),
+            )
+            .chain(self.ms_whql.map(Cap::Whql))
+            .chain(self.mccs_version.map(|(major, minor)| Cap::MccsVersion(major, minor)))
+            .chain(vcp)
+            .chain(iter::once(Cap::VcpNames(self.vcp_names().collect())))
+            .chain(self.edid.map(Cap::Edid))
+            .chain(self.vdif.iter().map(|&vdif| Cap::Vdif(vdif)))
+            .chain(self.windows.iter().cloned().map(Cap::Window))
+            .chain(self.asset_eeprom_size.map(Cap::AssetEeprom))
+            .chain(self.firmware.iter().cloned().map(Cap::Firmware))
+            .chain(self.unknown_tags.iter().copied().map(Cap::Unknown));
+
+        match capabilities_from_entries(entries.map(Ok::<_, Infallible>)) {
+            Ok(caps) => caps,
+            Err(e) => match e {},
+        }
+    }
+}
+
+impl From<&CapabilitiesRef<'_>> for Capabilities {
+    fn from(caps: &CapabilitiesRef<'_>) -> Self {
+        caps.to_capabilities()
+    }
+}
+
+/// A VCP feature borrowed from a capability string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VcpRef<'i> {
+    /// The feature code.
+    pub feature: u8,
+    values: Option<&'i [u8]>,
+}
+
+impl<'i> VcpRef<'i> {
+    /// The values allowed for the feature, if listed.
+    pub fn values(&self) -> impl Iterator<Item = VcpValueRef<'i>> + 'i {
+        items(self.values.unwrap_or_default(), vcp_value_ref)
+            .map(|(value, sub_values)| VcpValueRef { value, sub_values })
+    }
+}
+
+impl From<VcpRef<'_>> for Vcp {
+    fn from(vcp: VcpRef) -> Self {
+        Vcp {
+            feature: vcp.feature,
+            values: vcp.values.map(|_| vcp.values().map(Into::into).collect()),
+        }
+    }
+}
+
+/// A VCP feature value borrowed from a capability string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VcpValueRef<'i> {
+    /// The value.
+    pub value: u8,
+    sub_values: Option<&'i [u8]>,
+}
+
+impl<'i> VcpValueRef<'i> {
+    /// The sub-values listed after the value.
+    pub fn sub_values(&self) -> impl Iterator<Item = u8> + 'i {
+        items(self.sub_values.unwrap_or_default(), hexvalue)
+    }
+}
+
+impl From<VcpValueRef<'_>> for VcpValue {
+    fn from(value: VcpValueRef) -> Self {
+        VcpValue {
+            value: value.value,
+            sub_values: value.sub_values.map(|_| value.sub_values().collect()),
+        }
+    }
+}
+
+/// Lazily parses space separated items that have already been validated.
+fn items<'i, O, P>(mut i: &'i [u8], mut parser: P) -> impl Iterator<Item = O> + 'i
+where
+    P: FnMut(&'i [u8]) -> IResult<'i, O> + 'i,
+{
+    iter::from_fn(move || {
+        let (rest, item) = preceded(space0, &mut parser)(i).ok()?;
+        i = rest;
+        Some(item)
+    })
+}
+
+#[test]
+fn samples_borrowed() {
+    for sample in super::testdata::test_data() {
+        let owned = super::parse_capabilities(sample);
+        let borrowed = CapabilitiesRef::parse(sample);
+        match (owned, borrowed) {
+            (Ok(owned), Ok(borrowed)) => assert_eq!(borrowed.to_capabilities(), owned),
+            (Err(owned), Err(borrowed)) => assert_eq!(borrowed, owned),
+            (owned, borrowed) => panic!("{owned:?} != {borrowed:?}"),
+        }
+    }
+
+    let caps = CapabilitiesRef::parse("(prot(monitor)cmds(01 F3)vcp(10 14(05 08(01 02)) 60(0F 11)))").unwrap();
+    assert_eq!(caps.protocol.as_deref(), Some("monitor"));
+    assert_eq!(caps.commands().collect::<Vec<_>>(), [
+        Command::VcpRequest,
+        Command::CapabilitiesRequest
+    ]);
+    let vcp = caps.vcp_features().collect::<Vec<_>>();
+    assert_eq!(vcp.iter().map(|vcp| vcp.feature).collect::<Vec<_>>(), [
+        0x10, 0x14, 0x60
+    ]);
+    let values = vcp[1].values().collect::<Vec<_>>();
+    assert_eq!(values.iter().map(|v| v.value).collect::<Vec<_>>(), [0x05, 0x08]);
+    assert_eq!(values[1].sub_values().collect::<Vec<_>>(), [0x01, 0x02]);
+    assert_eq!(vcp[0].values().count(), 0);
+
+    for sample in ["(asset_eep(64)asset_eep(32))", "(asset_eep(64)asset_eep(040))"] {
+        let caps = CapabilitiesRef::parse(sample).unwrap();
+        assert_eq!(caps.asset_eeprom_size, Some(64));
+        assert_eq!(caps.unknown_tags.len(), 1);
+        assert_eq!(Capabilities::from(&caps), super::parse_capabilities(sample).unwrap());
+    }
+}
@@ -1,15 +1,26 @@
 use {
-    super::{bracketed, map_err, trim_spaces, OResult, Value, ValueParser},
+    super::{
+        bracketed,
+        entries::balancedparens,
+        error::{expect, Expected, NomError, ParseError},
+        many_all,
+        recover::{many_recover, Skipped},
+        span::{CapSpan, Origin, ValueSpan, VcpNameSpan, VcpSpan, VcpValueSpan},
+        trim_spaces, Charset, IResult, OResult, ParseOptions, Value, ValueParser,
+    },
+    mccs::{
+        Capabilities, Command, FirmwareTag, FirmwareVersion, UnknownData, UnknownTag, WindowArea, WindowCapability,
+    },
     nom::{
         branch::alt,
-        bytes::complete::{is_not, tag, take},
-        character::complete::{char, space1, u8},
-        combinator::{all_consuming, map, map_parser, map_res, opt, rest},
-        multi::{fold_many0, many0, separated_list0},
-        sequence::{separated_pair, tuple},
-        Finish, IResult,
+        bytes::complete::{is_not, tag, take, take_while},
+        character::complete::{alphanumeric1, char, digit1, space0, space1, u16, u32, u8},
+        combinator::{all_consuming, consumed, map, map_parser, map_res, opt, recognize, rest},
+        multi::{fold_many0, many0, many0_count, separated_list0},
+        sequence::{preceded, separated_pair, tuple},
+        Finish,
     },
-    std::{borrow::Cow, fmt, io, str},
+    std::{borrow::Cow, fmt, io, ops::Deref, str},
 };
 
 #[derive(Clone, PartialEq, Eq)]
@@ -31,6 +42,18 @@ impl VcpValue {
     }
 }
 
+impl fmt::Display for VcpValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02X}", self.value)?;
+        if let Some(sub_values) = &self.sub_values {
+            f.write_str("(")?;
+            write_hexarray(f, sub_values)?;
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Debug for VcpValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut debug = f.debug_tuple("VcpValue");
@@ -54,6 +77,23 @@ impl Vcp {
     }
 }
 
+impl fmt::Display for Vcp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02X}", self.feature)?;
+        if let Some(values) = &self.values {
+            f.write_str("(")?;
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(" ")?;
+                }
+                write!(f, "{value}")?;
+            }
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Debug for Vcp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut debug = f.debug_tuple("Vcp");
@@ -65,25 +105,101 @@ impl fmt::Debug for Vcp {
     }
 }
 
+/// Text from a capability string, along with the bytes it was decoded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Text<'i> {
+    /// The bytes of the text, after unescaping.
+    pub raw: Cow<'i, [u8]>,
+    /// The decoded text.
+    pub text: Cow<'i, str>,
+}
+
+impl<'i> Text<'i> {
+    /// Decodes `raw` as text in the given encoding.
+    pub fn decode(raw: Cow<'i, [u8]>, charset: Charset) -> Result<Self, str::Utf8Error> {
+        Self::decode_in(raw, |raw| charset.decode(raw))
+    }
+
+    fn decode_in<F>(raw: Cow<'i, [u8]>, decode: F) -> Result<Self, str::Utf8Error>
+    where
+        F: for<'a> Fn(&'a [u8]) -> Result<Cow<'a, str>, str::Utf8Error>,
+    {
+        let text = match raw {
+            Cow::Borrowed(raw) => decode(raw)?,
+            Cow::Owned(ref raw) => Cow::Owned(decode(raw)?.into_owned()),
+        };
+        Ok(Text { raw, text })
+    }
+}
+
+impl Deref for Text<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl<'i> From<&'i str> for Text<'i> {
+    fn from(text: &'i str) -> Self {
+        Text {
+            raw: Cow::Borrowed(text.as_bytes()),
+            text: Cow::Borrowed(text),
+        }
+    }
+}
+
+impl From<Text<'_>> for String {
+    fn from(text: Text) -> Self {
+        text.text.into_owned()
+    }
+}
+
+impl fmt::Display for Text<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct VcpName<'i> {
     pub feature: u8,
-    pub name: Option<Cow<'i, str>>,
-    pub value_names: Option<Vec<Cow<'i, str>>>,
+    pub name: Option<Text<'i>>,
+    pub value_names: Option<Vec<Text<'i>>>,
 }
 
 impl<'i> VcpName<'i> {
-    pub fn value_names(&self) -> &[Cow<'i, str>] {
+    pub fn value_names(&self) -> &[Text<'i>] {
         self.value_names.as_ref().map(|v| &v[..]).unwrap_or_default()
     }
 }
 
+impl fmt::Display for VcpName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02X}(", self.feature)?;
+        if let Some(name) = &self.name {
+            write!(f, "{}", Escaped(name))?;
+        }
+        if let Some(value_names) = &self.value_names {
+            f.write_str("(")?;
+            for (i, name) in value_names.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(" ")?;
+                }
+                write!(f, "{}", Escaped(name))?;
+            }
+            f.write_str(")")?;
+        }
+        f.write_str(")")
+    }
+}
+
 /// Parsed display capabilities string entry
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Cap<'a> {
-    Protocol(&'a str),
-    Type(&'a str),
-    Model(&'a str),
+    Protocol(Text<'a>),
+    Type(Text<'a>),
+    Model(Text<'a>),
     Commands(Vec<u8>),
     Whql(u8),
     MccsVersion(u8, u8),
@@ -91,14 +207,180 @@ pub enum Cap<'a> {
     VcpNames(Vec<VcpName<'a>>),
     Edid(&'a [u8]),
     Vdif(&'a [u8]),
+    Window(WindowCapability),
+    AssetEeprom(u16),
+    Firmware(FirmwareVersion),
     Unknown(Value<'a>),
 }
 
 impl<'i> Cap<'i> {
-    pub fn parse_entries(entries: ValueParser<'i>) -> impl Iterator<Item = io::Result<Cap<'i>>> + 'i {
-        entries
-            .nom_iter()
-            .map(|e| e.and_then(|e| Self::parse_entry(e)).map_err(map_err))
+    /// The entries that describe `caps`, in the order they are written to a
+    /// capability string.
+    ///
+    /// Value names are positional, so only the names preceding the first
+    /// unnamed value of a feature can be represented.
+    pub fn from_capabilities(caps: &'i Capabilities) -> impl Iterator<Item = Cap<'i>> + 'i {
+        let vcp = match caps.vcp_features.is_empty() {
+            true => None,
+            false => Some(Cap::Vcp(
+                caps.vcp_features
+                    .iter()
+                    .map(|(&feature, desc)| Vcp {
+                        feature,
+                        values: match desc.values.is_empty() {
+                            true => None,
+                            false => Some(
+                                desc.values()
+                                    .map(|&value| VcpValue {
+                                        value,
+                                        sub_values: desc.sub_values.get(&value).cloned(),
+                                    })
+                                    .collect(),
+                            ),
+                        },
+                    })
+                    .collect(),
+            )),
+        };
+        let vcpnames: Vec<_> = caps
+            .vcp_features
+            .iter()
+            .map(|(&feature, desc)| VcpName {
+                feature,
+                name: desc.name.as_deref().map(Text::from),
+                value_names: Some(
+                    desc.values
+                        .values()
+                        .map_while(|name| name.as_deref().map(Text::from))
+                        .collect::<Vec<_>>(),
+                )
+                .filter(|names| !names.is_empty()),
+            })
+            .filter(|name| name.name.is_some() || name.value_names.is_some())
+            .collect();
+        let vcpnames = match vcpnames.is_empty() {
+            true => None,
+            false => Some(Cap::VcpNames(vcpnames)),
+        };
+
+        caps.protocol
+            .as_ref()
+            .map(|protocol| Cap::Protocol(protocol.as_str().into()))
+            .into_iter()
+            .chain(caps.ty.as_ref().map(|ty| Cap::Type(ty.as_str().into())))
+            .chain(caps.model.as_deref().map(|model| Cap::Model(model.into())))
+            .chain(
+                Some(Cap::Commands(caps.commands.iter().map(Command::opcode).collect()))
+                    .filter(|_| !caps.commands.is_empty()),
+            )
+            .chain(vcp)
+            .chain(vcpnames)
+            .chain(
+                caps.mccs_version
+                    .map(|version| Cap::MccsVersion(version.major, version.minor)),
+            )
+            .chain(caps.windows.iter().cloned().map(Cap::Window))
+            .chain(caps.ms_whql.map(Cap::Whql))
+            .chain(caps.asset_eeprom_size.map(Cap::AssetEeprom))
+            .chain(caps.firmware.iter().cloned().map(Cap::Firmware))
+            .chain(caps.edid.as_deref().map(Cap::Edid))
+            .chain(caps.vdif.iter().map(|vdif| Cap::Vdif(vdif)))
+            .chain(caps.unknown_tags.iter().map(|tag| Cap::Unknown(tag.into())))
+    }
+
+    /// The name of this entry.
+    ///
+    /// This excludes the number of a `windowN()` entry.
+    pub fn tag(&self) -> &'i str {
+        match self {
+            Cap::Protocol(..) => "prot",
+            Cap::Type(..) => "type",
+            Cap::Model(..) => "model",
+            Cap::Commands(..) => "cmds",
+            Cap::Whql(..) => "mswhql",
+            Cap::MccsVersion(..) => "mccs_ver",
+            Cap::Vcp(..) => "vcp",
+            Cap::VcpNames(..) => "vcpname",
+            Cap::Edid(..) => "edid",
+            Cap::Vdif(..) => "vdif",
+            Cap::Window(..) => "window",
+            Cap::AssetEeprom(..) => "asset_eep",
+            Cap::Firmware(version) => version.tag.as_str(),
+            Cap::Unknown(value) => value.tag(),
+        }
+    }
+
+    /// Writes the entry in its capability string encoding.
+    ///
+    /// Unlike the `Display` implementation, binary and non-UTF-8 data is
+    /// written unescaped.
+    pub fn write<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        let (tag, data) = match *self {
+            Cap::Edid(data) => ("edid", data),
+            Cap::Vdif(data) => ("vdif", data),
+            Cap::Unknown(Value::Binary { tag, data }) => (tag, data),
+            Cap::Protocol(Text { ref raw, .. })
+            | Cap::Type(Text { ref raw, .. })
+            | Cap::Model(Text { ref raw, .. }) => {
+                write!(w, "{}(", self.tag())?;
+                EscapedText(raw).write(&mut w)?;
+                return w.write_all(b")")
+            },
+            Cap::Unknown(Value::String { tag, value }) => {
+                write!(w, "{tag}(")?;
+                w.write_all(value)?;
+                return w.write_all(b")")
+            },
+            Cap::Window(ref window) => {
+                let fields = window_fields(window);
+                write!(w, "window{}({}", window.window, fields.join(" "))?;
+                for (i, field) in window.unknown.iter().enumerate() {
+                    if i > 0 || !fields.is_empty() {
+                        w.write_all(b" ")?;
+                    }
+                    Cap::Unknown(field.into()).write(&mut w as &mut dyn io::Write)?;
+                }
+                return w.write_all(b")")
+            },
+            ref cap => return write!(w, "{cap}"),
+        };
+        write!(w, "{tag} bin({}(", data.len())?;
+        w.write_all(data)?;
+        w.write_all(b"))")
+    }
+
+    pub fn parse_entries(entries: ValueParser<'i>) -> impl Iterator<Item = Result<Cap<'i>, ParseError>> + 'i {
+        let parser = entries.clone();
+        entries.nom_iter().map(move |e| {
+            e.and_then(|value| Self::parse_entry_with(value, parser.options()))
+                .map_err(|e| parser.error(e))
+        })
+    }
+
+    /// Parses each entry along with its location in the capability string.
+    pub fn parse_entries_spanned(
+        entries: ValueParser<'i>,
+    ) -> impl Iterator<Item = Result<(Cap<'i>, CapSpan), ParseError>> + 'i {
+        let parser = entries.clone();
+        entries.spanned().map(move |e| {
+            let (value, entry) = e?;
+            Self::parse_entry_spanned(value, entry, parser.options()).map_err(|e| parser.error(e))
+        })
+    }
+
+    /// Parses an entry found at `entry`, locating its nested items.
+    pub(crate) fn parse_entry_spanned(
+        value: Value<'i>,
+        entry: ValueSpan,
+        options: &ParseOptions,
+    ) -> OResult<'i, (Cap<'i>, CapSpan)> {
+        let (cap, vcp, vcp_names) = match (options.known_tag(value.tag()), value) {
+            (None, value) => (Cap::Unknown(value), Vec::new(), Vec::new()),
+            (Some(tag), Value::String { value, .. }) =>
+                Self::parse_string_in(tag, value, entry.contents.start, options.charset)?,
+            (Some(tag), Value::Binary { data, .. }) => (Self::parse_data(tag, data), Vec::new(), Vec::new()),
+        };
+        Ok((cap, CapSpan { entry, vcp, vcp_names }))
     }
 
     pub fn parse_entry(value: Value<'i>) -> OResult<'i, Cap<'i>> {
@@ -108,6 +390,15 @@ impl<'i> Cap<'i> {
         }
     }
 
+    /// Parses an entry, recognizing its name as specified by `options`.
+    pub fn parse_entry_with(value: Value<'i>, options: &ParseOptions) -> OResult<'i, Cap<'i>> {
+        match (options.known_tag(value.tag()), value) {
+            (None, value) => Ok(Cap::Unknown(value)),
+            (Some(tag), Value::String { value, .. }) => Self::parse_string_with(tag, value, options.charset),
+            (Some(tag), Value::Binary { data, .. }) => Ok(Self::parse_data(tag, data)),
+        }
+    }
+
     pub fn parse_data(tag: &'i str, i: &'i [u8]) -> Cap<'i> {
         match tag {
             "edid" => Cap::Edid(i),
@@ -117,117 +408,524 @@ impl<'i> Cap<'i> {
     }
 
     pub fn parse_string(tag: &'i str, i: &'i [u8]) -> OResult<'i, Cap<'i>> {
+        Self::parse_string_with(tag, i, Default::default())
+    }
+
+    /// Parses an entry, decoding its text in the given encoding.
+    pub fn parse_string_with(tag: &'i str, i: &'i [u8], charset: Charset) -> OResult<'i, Cap<'i>> {
+        Self::parse_string_in(tag, i, 0, charset).map(|(cap, ..)| cap)
+    }
+
+    /// Parses an entry, locating its `vcp` and `vcpname` items relative to
+    /// `offset`, the position of its contents.
+    fn parse_string_in(tag: &'i str, i: &'i [u8], offset: usize, charset: Charset) -> ParsedString<'i> {
+        let origin = Origin::new(i, offset);
+        let unspanned = |cap| (cap, Vec::new(), Vec::new());
         match tag {
-            "prot" => all_consuming(map(value, Cap::Protocol))(i),
-            "type" => all_consuming(map(value, Cap::Type))(i),
-            "model" => all_consuming(map(value, Cap::Model))(i),
-            "cmds" => all_consuming(map(hexarray, Cap::Commands))(i),
-            "mswhql" => all_consuming(map(map_parser(take(1usize), u8), Cap::Whql))(i),
-            "mccs_ver" => all_consuming(map(mccs_ver, |(major, minor)| Cap::MccsVersion(major, minor)))(i),
+            "prot" => all_consuming(map(map(text(Charset::Utf8), Cap::Protocol), unspanned))(i),
+            "type" => all_consuming(map(map(text(Charset::Utf8), Cap::Type), unspanned))(i),
+            "model" => all_consuming(map(map(text(charset), Cap::Model), unspanned))(i),
+            "cmds" => map(map(hexarray, Cap::Commands), unspanned)(i),
+            "mswhql" => all_consuming(map(
+                map(expect(Expected::Decimal, map_parser(take(1usize), u8)), Cap::Whql),
+                unspanned,
+            ))(i),
+            "mccs_ver" => all_consuming(map(
+                map(mccs_ver, |(major, minor)| Cap::MccsVersion(major, minor)),
+                unspanned,
+            ))(i),
             // hack for Apple Cinema Display
-            "vcp" | "VCP" => all_consuming(map(trim_spaces(many0(vcp)), Cap::Vcp))(i),
-            "vcpname" => all_consuming(map(many0(vcpname), Cap::VcpNames))(i),
-            _ => Ok((Default::default(), Cap::Unknown(Value::String { tag, value: i }))),
+            "vcp" | "VCP" => map(many_all(vcp(origin)), |vcp| {
+                let (vcp, spans) = vcp.into_iter().unzip();
+                (Cap::Vcp(vcp), spans, Vec::new())
+            })(i),
+            "vcpname" => map(many_all(vcpname(origin, charset)), |names| {
+                let (names, spans) = names.into_iter().unzip();
+                (Cap::VcpNames(names), Vec::new(), spans)
+            })(i),
+            _ if window_number(tag).is_some() => map(map(window(tag), Cap::Window), unspanned)(i),
+            // vendor entries are kept as unknown if they can't be parsed
+            "asset_eep" => map(
+                alt((
+                    all_consuming(map(u16, Cap::AssetEeprom)),
+                    map(rest, |value| Cap::Unknown(Value::String { tag, value })),
+                )),
+                unspanned,
+            )(i),
+
+            _ => match firmware_tag(tag) {
+                Some(firmware) => map(
+                    alt((
+                        map(firmware_version(firmware), Cap::Firmware),
+                        map(rest, |value| Cap::Unknown(Value::String { tag, value })),
+                    )),
+                    unspanned,
+                )(i),
+                None => Ok((
+                    Default::default(),
+                    unspanned(Cap::Unknown(Value::String { tag, value: i })),
+                )),
+            },
         }
         .finish()
         .map(|(_, c)| c)
+        .map_err(|e| e.with_tag(tag))
     }
+
+    /// Parses an entry, skipping over malformed items in `vcp`, `vcpname` and
+    /// `cmds` entries.
+    ///
+    /// Returns `None` if nothing could be salvaged, along with each error and
+    /// the input that was skipped because of it.
+    pub(crate) fn parse_string_recover(
+        tag: &'i str,
+        i: &'i [u8],
+        charset: Charset,
+    ) -> (Option<Cap<'i>>, Vec<Skipped<'i>>) {
+        let e = match Self::parse_string_with(tag, i, charset) {
+            Ok(cap) => return (Some(cap), Vec::new()),
+            Err(e) => e,
+        };
+        let (cap, skipped) = match tag {
+            "cmds" => {
+                let (cmds, skipped) = many_recover(hexvalue, i);
+                (Some(Cap::Commands(cmds)), skipped)
+            },
+            "vcp" | "VCP" => {
+                let (vcp, skipped) = many_recover(vcp(Origin::new(i, 0)), i);
+                (Some(Cap::Vcp(vcp.into_iter().map(|(vcp, _)| vcp).collect())), skipped)
+            },
+            "vcpname" => {
+                let (names, skipped) = many_recover(vcpname(Origin::new(i, 0), charset), i);
+                (
+                    Some(Cap::VcpNames(names.into_iter().map(|(name, _)| name).collect())),
+                    skipped,
+                )
+            },
+            _ => (None, vec![(e, i)]),
+        };
+        let skipped = skipped.into_iter().map(|(e, i)| (e.with_tag(tag), i)).collect();
+        (cap, skipped)
+    }
+}
+
+impl fmt::Display for Cap<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tag = self.tag();
+        match self {
+            Cap::Protocol(value) | Cap::Type(value) | Cap::Model(value) =>
+                write!(f, "{tag}({})", EscapedText(value.as_bytes())),
+            Cap::Commands(cmds) => {
+                write!(f, "{tag}(")?;
+                write_hexarray(f, cmds)?;
+                f.write_str(")")
+            },
+            Cap::Whql(whql) => write!(f, "{tag}({whql})"),
+            Cap::AssetEeprom(size) => write!(f, "{tag}({size})"),
+            Cap::Firmware(version) => write!(f, "{tag}({version})"),
+            Cap::MccsVersion(major, minor) => write!(f, "{tag}({major}.{minor})"),
+            Cap::Vcp(vcp) => {
+                write!(f, "{tag}(")?;
+                for (i, vcp) in vcp.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{vcp}")?;
+                }
+                f.write_str(")")
+            },
+            Cap::VcpNames(names) => {
+                write!(f, "{tag}(")?;
+                for name in names {
+                    write!(f, "{name}")?;
+                }
+                f.write_str(")")
+            },
+            &Cap::Edid(data) | &Cap::Vdif(data) => write!(f, "{}", Value::Binary { tag, data }),
+            Cap::Window(window) => {
+                let mut fields = window_fields(window);
+                fields.extend(window.unknown.iter().map(|field| Value::from(field).to_string()));
+                write!(f, "{tag}{}({})", window.window, fields.join(" "))
+            },
+            Cap::Unknown(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// The recognized fields of a `windowN()` entry, as written in a capability
+/// string.
+fn window_fields(window: &WindowCapability) -> Vec<String> {
+    let mut fields = Vec::new();
+    if let Some(ty) = &window.ty {
+        fields.push(format!("type({})", Escaped(ty.as_str())));
+    }
+    if let Some(WindowArea {
+        left,
+        top,
+        right,
+        bottom,
+    }) = window.area
+    {
+        fields.push(format!("area({left} {top} {right} {bottom})"));
+    }
+    if let Some((width, height)) = window.max_size {
+        fields.push(format!("max({width} {height})"));
+    }
+    if let Some((width, height)) = window.min_size {
+        fields.push(format!("min({width} {height})"));
+    }
+    if !window.features.is_empty() {
+        let features: Vec<_> = window.features.iter().map(|f| format!("{f:02X}")).collect();
+        fields.push(format!("window({})", features.join(" ")));
+    }
+    fields
+}
+
+fn write_hexarray(f: &mut fmt::Formatter, values: &[u8]) -> fmt::Result {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            f.write_str(" ")?;
+        }
+        write!(f, "{value:02X}")?;
+    }
+    Ok(())
 }
 
-fn backslash_escape(i: &[u8]) -> IResult<&[u8], String> {
+/// Formats a name as it appears in a `vcpname()` entry, the inverse of
+/// `value_escape_nospace`.
+pub(crate) struct Escaped<'a>(pub &'a str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                ' ' | '(' | ')' | '\\' | '\x00'..='\x1f' | '\x7f' => write!(f, "\\x{:02X}", c as u32)?,
+                c if c.is_ascii() => write!(f, "{c}")?,
+                c =>
+                    for b in c.encode_utf8(&mut [0; 4]).bytes() {
+                        write!(f, "\\x{b:02X}")?;
+                    },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the text of an entry such as `model()`, escaping the characters
+/// that would otherwise end it early or be mistaken for an escape.
+pub(crate) struct EscapedText<'a>(pub &'a [u8]);
+
+impl EscapedText<'_> {
+    const SPECIAL: &'static [u8] = b"()\\";
+
+    fn write<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        for chunk in self.0.split_inclusive(|c| Self::SPECIAL.contains(c)) {
+            match chunk.split_last() {
+                Some((&c, chunk)) if Self::SPECIAL.contains(&c) => {
+                    w.write_all(chunk)?;
+                    write!(w, "\\x{c:02X}")?;
+                },
+                _ => w.write_all(chunk)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for EscapedText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text = Vec::new();
+        self.write(&mut text).map_err(|_| fmt::Error)?;
+        f.write_str(&String::from_utf8_lossy(&text))
+    }
+}
+
+type ParsedString<'i> = OResult<'i, (Cap<'i>, Vec<VcpSpan>, Vec<VcpNameSpan>)>;
+
+fn backslash_escape(i: &[u8]) -> IResult<'_, Vec<u8>> {
     // TODO: I'd use https://docs.rs/nom/7.1.1/nom/bytes/complete/fn.escaped_transform.html instead,
     // but it can't deal with dynamic transforms due to ExtendInto not being impl'd on anything useful
     // like Vec<u8> or [u8; N] or something...
     let escaped = |i| {
         let (i, _) = tag("\\x")(i)?;
-        map_str(take(2usize), |h| u8::from_str_radix(h, 16).map(|v| v as char), i)
+        map_str(take(2usize), |h| u8::from_str_radix(h, 16), i)
     };
     fold_many0(
         alt((
             escaped,
             // TODO: other escapes like \\ \n etc? unclear in access bus spec...
-            map(take(1usize), |s: &[u8]| s[0] as char), // TODO, this isn't utf8 parsing, should it be? .-.
+            map(take(1usize), |s: &[u8]| s[0]),
         )),
-        || String::new(),
-        |mut s: String, c| {
+        Vec::new,
+        |mut s: Vec<u8>, c| {
             s.push(c);
             s
         },
     )(i)
 }
 
-fn value_escape_nospace(i: &[u8]) -> IResult<&[u8], Cow<str>> {
-    map_parser(
-        is_not(" ()"),
-        alt((
-            all_consuming(map(map_res(is_not("\\"), str::from_utf8), Cow::Borrowed)),
-            map(all_consuming(backslash_escape), Cow::Owned),
-        )),
-    )(i)
+fn value_escape_nospace<'i>(charset: Charset) -> impl FnMut(&'i [u8]) -> IResult<'i, Text<'i>> {
+    move |i| {
+        let (rest, raw) = is_not(" ()")(i)?;
+        let unescaped = match raw.contains(&b'\\') {
+            false => Cow::Borrowed(raw),
+            true => Cow::Owned(all_consuming(backslash_escape)(raw)?.1),
+        };
+        let escaped = matches!(unescaped, Cow::Owned(..));
+        match Text::decode_in(unescaped, |raw| charset.decode_name(raw)) {
+            Ok(text) => Ok((rest, text)),
+            Err(e) => Err(nom::Err::Failure(NomError::new(
+                if escaped { raw } else { &raw[e.valid_up_to()..] },
+                Expected::Utf8,
+            ))),
+        }
+    }
 }
 
-fn value(i: &[u8]) -> IResult<&[u8], &str> {
-    map_res(rest, str::from_utf8)(i)
+/// The text of an entry, unescaping it as written by [`EscapedText`].
+fn text<'i>(charset: Charset) -> impl FnMut(&'i [u8]) -> IResult<'i, Text<'i>> {
+    move |i| {
+        let unescaped = match i.contains(&b'\\') {
+            false => Cow::Borrowed(i),
+            true => Cow::Owned(all_consuming(backslash_escape)(i)?.1),
+        };
+        let escaped = matches!(unescaped, Cow::Owned(..));
+        match Text::decode(unescaped, charset) {
+            Ok(text) => Ok((&i[i.len()..], text)),
+            Err(e) => Err(nom::Err::Error(NomError::new(
+                if escaped { i } else { &i[e.valid_up_to()..] },
+                Expected::Utf8,
+            ))),
+        }
+    }
 }
 
-fn hexarray(i: &[u8]) -> IResult<&[u8], Vec<u8>> {
-    many0(trim_spaces(hexvalue))(i)
+fn value(i: &[u8]) -> IResult<'_, &str> {
+    match str::from_utf8(i) {
+        Ok(s) => Ok((&i[i.len()..], s)),
+        Err(e) => Err(nom::Err::Error(NomError::new(&i[e.valid_up_to()..], Expected::Utf8))),
+    }
+}
+
+fn hexarray(i: &[u8]) -> IResult<'_, Vec<u8>> {
+    many_all(hexvalue)(i)
 }
 
-fn map_str<'i, O, E2, F, G>(mut parser: F, f: G, i: &'i [u8]) -> IResult<&'i [u8], O>
+fn map_str<'i, O, E2, F, G>(mut parser: F, f: G, i: &'i [u8]) -> IResult<'i, O>
 where
-    F: nom::Parser<&'i [u8], &'i [u8], nom::error::Error<&'i [u8]>>,
+    F: nom::Parser<&'i [u8], &'i [u8], NomError<'i>>,
     G: FnMut(&'i str) -> Result<O, E2>,
 {
     use nom::Parser;
 
     let mut f = map_res(rest, f);
-    let (i, s) = map_res(|i| parser.parse(i), |i| str::from_utf8(i.into()))(i)?;
+    let (rest, s) = map_res(|i| parser.parse(i), str::from_utf8)(i)?;
     match f.parse(s) {
-        Ok((_, v)) => Ok((i, v)),
-        Err(e) => Err(e.map(|e: nom::error::Error<_>| nom::error::Error { input: i, code: e.code })),
+        Ok((_, v)) => Ok((rest, v)),
+        Err(e) => Err(e.map(|_: nom::error::Error<_>| NomError::new(i, Expected::Other))),
     }
 }
 
-fn hexvalue(i: &[u8]) -> IResult<&[u8], u8> {
-    map_str(take(2usize), |s| u8::from_str_radix(s, 16), i)
+pub(crate) fn hexvalue(i: &[u8]) -> IResult<'_, u8> {
+    expect(Expected::Hex, |i| {
+        map_str(take(2usize), |s| u8::from_str_radix(s, 16), i)
+    })(i)
 }
 
-fn vcp_value(i: &[u8]) -> IResult<&[u8], VcpValue> {
+fn vcp_value<'i>(origin: Origin<'i>) -> impl FnMut(&'i [u8]) -> IResult<'i, (VcpValue, VcpValueSpan)> {
     map(
-        tuple((trim_spaces(hexvalue), opt(bracketed(many0(trim_spaces(hexvalue)))))),
-        |(value, sub_values)| VcpValue { value, sub_values },
-    )(i)
+        trim_spaces(origin.located(tuple((
+            origin.located(hexvalue),
+            opt(preceded(
+                space0,
+                bracketed(many0(trim_spaces(origin.located(hexvalue)))),
+            )),
+        )))),
+        move |(all, ((value_i, value), sub_values))| {
+            let sub_value_spans = sub_values.iter().flatten().map(|(i, _)| i.clone()).collect();
+            (
+                VcpValue {
+                    value,
+                    sub_values: sub_values.map(|sub_values| sub_values.into_iter().map(|(_, v)| v).collect()),
+                },
+                VcpValueSpan {
+                    span: all,
+                    value: value_i,
+                    sub_values: sub_value_spans,
+                },
+            )
+        },
+    )
+}
+
+/// A `vcp` item, leaving its values unparsed.
+pub(crate) fn vcp_ref(i: &[u8]) -> IResult<'_, (u8, Option<&[u8]>)> {
+    trim_spaces(tuple((
+        hexvalue,
+        opt(preceded(space0, bracketed(recognize(many0_count(vcp_value_ref))))),
+    )))(i)
+}
+
+/// A `vcp` value, leaving its sub-values unparsed.
+pub(crate) fn vcp_value_ref(i: &[u8]) -> IResult<'_, (u8, Option<&[u8]>)> {
+    trim_spaces(tuple((
+        hexvalue,
+        opt(preceded(
+            space0,
+            bracketed(recognize(many0_count(trim_spaces(hexvalue)))),
+        )),
+    )))(i)
 }
 
-fn vcp(i: &[u8]) -> IResult<&[u8], Vcp> {
-    let featurevalues = bracketed(many0(trim_spaces(vcp_value)));
+fn vcp<'i>(origin: Origin<'i>) -> impl FnMut(&'i [u8]) -> IResult<'i, (Vcp, VcpSpan)> {
+    let featurevalues = bracketed(many0(vcp_value(origin)));
     map(
-        tuple((trim_spaces(hexvalue), opt(featurevalues))),
-        |(feature, values)| Vcp { feature, values },
-    )(i)
+        trim_spaces(origin.located(tuple((origin.located(hexvalue), opt(preceded(space0, featurevalues)))))),
+        move |(all, ((feature_i, feature), values))| {
+            let (values, value_spans) = match values {
+                Some(values) => {
+                    let (values, spans) = values.into_iter().unzip();
+                    (Some(values), spans)
+                },
+                None => (None, Vec::new()),
+            };
+            (Vcp { feature, values }, VcpSpan {
+                span: all,
+                feature: feature_i,
+                values: value_spans,
+            })
+        },
+    )
+}
+
+pub(crate) fn vcpname<'i>(
+    origin: Origin<'i>,
+    charset: Charset,
+) -> impl FnMut(&'i [u8]) -> IResult<'i, (VcpName<'i>, VcpNameSpan)> {
+    map(
+        trim_spaces(origin.located(tuple((
+            origin.located(hexvalue),
+            preceded(
+                space0,
+                bracketed(tuple((
+                    opt(origin.located(value_escape_nospace(charset))),
+                    opt(bracketed(trim_spaces(separated_list0(
+                        space1,
+                        origin.located(value_escape_nospace(charset)),
+                    )))),
+                ))),
+            ),
+        )))),
+        move |(all, ((feature_i, feature), (name, value_names)))| {
+            let value_name_spans = value_names.iter().flatten().map(|(i, _)| i.clone()).collect();
+            (
+                VcpName {
+                    feature,
+                    name: name.as_ref().map(|(_, name)| name.clone()),
+                    value_names: value_names.map(|names| names.into_iter().map(|(_, name)| name).collect()),
+                },
+                VcpNameSpan {
+                    span: all,
+                    feature: feature_i,
+                    name: name.map(|(i, _)| i),
+                    value_names: value_name_spans,
+                },
+            )
+        },
+    )
 }
 
-fn vcpname(i: &[u8]) -> IResult<&[u8], VcpName> {
-    let (i, feature) = trim_spaces(hexvalue)(i)?;
-    let (i, (name, value_names)) = bracketed(tuple((
-        opt(value_escape_nospace),
-        opt(bracketed(trim_spaces(separated_list0(space1, value_escape_nospace)))),
-    )))(i)?;
-    Ok((i, VcpName {
-        feature,
-        name,
-        value_names,
-    }))
+/// The number of a `windowN()` entry.
+pub(crate) fn window_number(tag: &str) -> Option<u8> {
+    match tag.split_at_checked(6) {
+        Some((window, number))
+            if window.eq_ignore_ascii_case("window") && number.bytes().all(|c| c.is_ascii_digit()) =>
+            number.parse().ok(),
+        _ => None,
+    }
 }
 
-fn mccs_ver(i: &[u8]) -> IResult<&[u8], (u8, u8)> {
-    alt((
-        separated_pair(u8, char('.'), u8),
-        tuple((map_parser(take(2usize), u8), map_parser(take(2usize), u8))),
+fn window<'i>(tag: &'i str) -> impl FnMut(&'i [u8]) -> IResult<'i, WindowCapability> {
+    move |i| {
+        let mut window = WindowCapability {
+            window: window_number(tag).unwrap_or_default(),
+            ..Default::default()
+        };
+        let size = |i| all_consuming(trim_spaces(expect(Expected::Decimal, separated_pair(u16, space1, u16))))(i);
+        let (i, fields) = many_all(window_field)(i)?;
+        for (name, contents) in fields {
+            match name {
+                "type" => {
+                    let (_, ty) = all_consuming(backslash_escape)(value(contents)?.1.trim().as_bytes())?;
+                    window.ty = Some(String::from_utf8_lossy(&ty).as_ref().into())
+                },
+                "area" => {
+                    let (_, (left, top, right, bottom)) = all_consuming(trim_spaces(expect(
+                        Expected::Decimal,
+                        tuple((u16, preceded(space1, u16), preceded(space1, u16), preceded(space1, u16))),
+                    )))(contents)?;
+                    window.area = Some(WindowArea {
+                        left,
+                        top,
+                        right,
+                        bottom,
+                    })
+                },
+                "max" => window.max_size = Some(size(contents)?.1),
+                "min" => window.min_size = Some(size(contents)?.1),
+                "window" => window.features = hexarray(contents)?.1,
+                _ => window.unknown.push(UnknownTag {
+                    name: name.into(),
+                    data: match str::from_utf8(contents) {
+                        Ok(contents) => UnknownData::String(contents.into()),
+                        Err(..) => UnknownData::StringBytes(contents.into()),
+                    },
+                }),
+            }
+        }
+        Ok((i, window))
+    }
+}
+
+fn window_field(i: &[u8]) -> IResult<'_, (&str, &[u8])> {
+    trim_spaces(separated_pair(
+        expect(Expected::Tag, map_res(alphanumeric1, str::from_utf8)),
+        space0,
+        bracketed(balancedparens),
     ))(i)
 }
 
+/// The firmware entry named `tag`, if it is one.
+pub(crate) fn firmware_tag(tag: &str) -> Option<FirmwareTag> {
+    FirmwareTag::ALL.into_iter().find(|firmware| firmware.as_str() == tag)
+}
+
+fn firmware_version<'i>(tag: FirmwareTag) -> impl FnMut(&'i [u8]) -> IResult<'i, FirmwareVersion> {
+    map(
+        all_consuming(consumed(tuple((
+            take_while(|c: u8| c.is_ascii_alphabetic()),
+            u32,
+            opt(preceded(char('.'), digit1)),
+        )))),
+        move |(version, (_, major, minor))| FirmwareVersion {
+            tag,
+            version: String::from_utf8_lossy(version).into(),
+            major,
+            minor: minor.map(|minor| String::from_utf8_lossy(minor).into()),
+        },
+    )
+}
+
+fn mccs_ver(i: &[u8]) -> IResult<'_, (u8, u8)> {
+    expect(
+        Expected::Version,
+        alt((
+            separated_pair(u8, char('.'), u8),
+            tuple((map_parser(take(2usize), u8), map_parser(take(2usize), u8))),
+        )),
+    )(i)
+}
+
 #[test]
 fn vcpname_temp() {
     let testdata = br"14((9300 6500 5500))44(Rotate)80(Do\x20this(On Off))82(Fixit)";
@@ -254,14 +952,26 @@ fn vcpname_temp() {
         },
     ];
 
-    let (_, vcps) = all_consuming(many0(vcpname))(testdata).finish().unwrap();
+    let (_, vcps) = all_consuming(many0(vcpname(Origin::new(testdata, 0), Default::default())))(testdata)
+        .finish()
+        .unwrap();
     assert_eq!(vcps.len(), expected.len());
 
-    for (vcp, exp) in vcps.into_iter().zip(expected) {
+    for ((vcp, _), exp) in vcps.into_iter().zip(expected) {
         assert_eq!(vcp, exp);
     }
 }
 
+#[test]
+fn vcpname_display() {
+    let testdata = br"14((9300 6500 5500))44(Rotate)80(Do\x20this(On Off))82(Fixit)90(A\x28B\x29\x5C(x\x20y))";
+    let (_, vcps) = all_consuming(many0(vcpname(Origin::new(testdata, 0), Default::default())))(testdata)
+        .finish()
+        .unwrap();
+    let written: String = vcps.iter().map(|(vcp, _)| vcp.to_string()).collect();
+    assert_eq!(written.as_bytes(), testdata);
+}
+
 #[test]
 fn vcpname_brightness() {
     let testdata = b"10(Brightness)";
@@ -270,7 +980,149 @@ fn vcpname_brightness() {
         name: Some("Brightness".into()),
         value_names: None,
     };
-    let (_, vcp) = all_consuming(vcpname)(testdata).finish().unwrap();
+    let (_, (vcp, span)) = all_consuming(vcpname(Origin::new(testdata, 0), Default::default()))(testdata)
+        .finish()
+        .unwrap();
 
     assert_eq!(vcp, expected);
+    assert_eq!(span, VcpNameSpan {
+        span: 0..14,
+        feature: 0..2,
+        name: Some(3..13),
+        value_names: Vec::new(),
+    });
+}
+
+#[test]
+fn window_mccs_spec() {
+    use mccs::WindowType;
+
+    let testdata = b"type (PIP) area(25 25 1895 1175) max(640 480) min(10 10) window(10)";
+    let window = WindowCapability {
+        window: 1,
+        ty: Some(WindowType::Pip),
+        area: Some(WindowArea {
+            left: 25,
+            top: 25,
+            right: 1895,
+            bottom: 1175,
+        }),
+        max_size: Some((640, 480)),
+        min_size: Some((10, 10)),
+        features: vec![0x10],
+        unknown: Vec::new(),
+    };
+    let caps = crate::parse_capabilities(super::testdata::test_data()[5]).unwrap();
+    assert_eq!(caps.windows, std::slice::from_ref(&window));
+    let cap = Cap::parse_string("window1", testdata).unwrap();
+    assert_eq!(cap, Cap::Window(window));
+    assert_eq!(
+        cap.to_string(),
+        "window1(type(PIP) area(25 25 1895 1175) max(640 480) min(10 10) window(10))"
+    );
+
+    assert!(Cap::parse_string("window2", b"area(1 2 3)").is_err());
+    assert_eq!(
+        Cap::parse_string("window3", b"type(PBP) size(1 2)"),
+        Ok(Cap::Window(WindowCapability {
+            window: 3,
+            ty: Some(WindowType::Pbp),
+            unknown: vec![UnknownTag {
+                name: "size".into(),
+                data: UnknownData::String("1 2".into()),
+            }],
+            ..Default::default()
+        }))
+    );
+
+    let cap = Cap::Window(WindowCapability {
+        window: 4,
+        ty: Some(WindowType::Unknown("P(I)P\\ 2".into())),
+        ..Default::default()
+    });
+    assert_eq!(cap.to_string(), "window4(type(P\\x28I\\x29P\\x5C\\x202))");
+    assert_eq!(Cap::parse_string("window4", b"type(P\\x28I\\x29P\\x5C\\x202)"), Ok(cap));
+    let cap = Cap::parse_string("window5", b"type(PIP) size(1 2) z(a(b))").unwrap();
+    assert_eq!(cap.to_string(), "window5(type(PIP) size(1 2) z(a(b)))");
+}
+
+#[test]
+fn vendor_tags() {
+    let samples = super::testdata::test_data();
+    let sample = |needle: &[u8]| {
+        let sample = samples
+            .iter()
+            .find(|s| s.windows(needle.len()).any(|w| w == needle))
+            .unwrap();
+        crate::parse_capabilities(sample).unwrap()
+    };
+
+    let caps = sample(b"mpu_ver(V2.00)");
+    assert_eq!(caps.asset_eeprom_size, Some(64));
+    assert_eq!(caps.firmware, [FirmwareVersion {
+        tag: FirmwareTag::MpuVersion,
+        version: "V2.00".into(),
+        major: 2,
+        minor: Some("00".into()),
+    }]);
+    assert!(caps.unknown_tags.is_empty());
+
+    let caps = sample(b"mpu(001)");
+    assert_eq!(caps.firmware[0].tag, FirmwareTag::Mpu);
+    assert_eq!((caps.firmware[0].major, caps.firmware[0].minor.as_deref()), (1, None));
+
+    let sample = "(asset_eep(64)mpu_ver(2.05)mpu_ver(2.5)asset_eep(040))";
+    let caps = crate::parse_capabilities(sample).unwrap();
+    let minor: Vec<_> = caps.firmware.iter().map(|fw| fw.minor.as_deref()).collect();
+    assert_eq!(minor, [Some("05"), Some("5")]);
+    assert_ne!(caps.firmware[0], caps.firmware[1]);
+    assert_eq!(caps.asset_eeprom_size, Some(64));
+    assert_eq!(caps.unknown_tags, [mccs::UnknownTag {
+        name: "asset_eep".into(),
+        data: mccs::UnknownData::String("040".into()),
+    }]);
+    assert_eq!(crate::write_capabilities(&caps), sample.as_bytes());
+    let (recovered, _) = crate::parse_capabilities_recover(sample);
+    assert_eq!(recovered, caps);
+    assert_eq!(crate::CapabilityTree::parse(sample).unwrap().capabilities(), caps);
+    let mut parser = crate::IncrementalParser::new();
+    parser.push(sample.as_bytes()).for_each(drop);
+    assert_eq!(parser.capabilities(), caps);
+
+    let caps = crate::parse_capabilities("(fw(beta)asset_eep(big)version(1.2.3))").unwrap();
+    assert!(caps.firmware.is_empty());
+    assert_eq!(caps.asset_eeprom_size, None);
+    let unknown: Vec<_> = caps.unknown_tags.iter().map(|tag| &tag.name[..]).collect();
+    assert_eq!(unknown, ["fw", "asset_eep", "version"]);
+}
+
+#[test]
+fn charsets() {
+    let model = |charset| match Cap::parse_string_with("model", b"M\xfcnchen", charset) {
+        Ok(Cap::Model(model)) => Ok(model),
+        res => Err(res),
+    };
+    let latin1 = model(Charset::Latin1).unwrap();
+    assert_eq!((&latin1[..], &latin1.raw[..]), ("München", &b"M\xfcnchen"[..]));
+    assert_eq!(&model(Charset::LossyUtf8).unwrap()[..], "M\u{fffd}nchen");
+    assert!(model(Charset::Utf8).is_err());
+    assert!(model(Charset::Compatible).is_err());
+
+    let names = "vcpname(10(Gr\\xC3\\xBC\\xC3\\x9Fe) 14(明るさ(暖色 寒色)))";
+    let caps = crate::parse_capabilities(format!("(vcp(10 14(01 02)){names})")).unwrap();
+    assert_eq!(caps.vcp_features[&0x10].name.as_deref(), Some("Grüße"));
+    assert_eq!(caps.vcp_features[&0x14].name.as_deref(), Some("明るさ"));
+    assert_eq!(caps.vcp_features[&0x14].values[&0x02].as_deref(), Some("寒色"));
+
+    let escaped = &b"10(Gr\\xFC\\xDFe)"[..];
+    let (_, (name, _)) = vcpname(Origin::new(escaped, 0), Charset::Latin1)(escaped).unwrap();
+    let name = name.name.unwrap();
+    assert_eq!((&name[..], &name.raw[..]), ("Grüße", &b"Gr\xfc\xdfe"[..]));
+    assert_eq!(Escaped("Grüße").to_string(), "Gr\\xC3\\xBC\\xC3\\x9Fe");
+    assert!(vcpname(Origin::new(escaped, 0), Charset::Utf8)(escaped).is_err());
+
+    let caps = crate::parse_capabilities(br"(vcp(10)vcpname(10(Gr\xFC\xDFe)))").unwrap();
+    assert_eq!(caps.vcp_features[&0x10].name.as_deref(), Some("Grüße"));
+    let caps = crate::parse_capabilities(&b"(vcp(10)vcpname(10(M\xfcnchen)))"[..]).unwrap();
+    assert_eq!(caps.vcp_features[&0x10].name.as_deref(), Some("München"));
 }
@@ -0,0 +1,580 @@
+use {
+    super::{
+        capabilities_from_entries, caps::Escaped, Cap, CapSpan, ParseError, ParseOptions, Repeats, Span, Value,
+        ValueParser, VcpSpan,
+    },
+    mccs::{Capabilities, FeatureCode, Version},
+    std::{collections::BTreeSet, convert::Infallible, str},
+};
+
+/// A lossless representation of a capability string that supports editing.
+///
+/// Edits only rewrite the bytes of the entries they affect, so the original
+/// whitespace, hex case, missing or extra outer brackets, repeated entries and
+/// unrecognized tags are all preserved. An unedited tree reproduces its input
+/// exactly.
+///
+/// The capability string is parsed once, and each edit only parses the
+/// entries it changes again.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CapabilityTree {
+    source: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+/// What the tree knows about an entry of the capability string.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Entry {
+    /// The location of the entry and its items.
+    span: CapSpan,
+    /// Whether the entry contains binary data.
+    binary: bool,
+    /// What the entry describes.
+    kind: EntryKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum EntryKind {
+    /// A `vcp()` entry, along with the values declared for each feature.
+    Vcp(Vec<(FeatureCode, Vec<u8>)>),
+    /// A `vcpname()` entry, along with whether each feature names its values.
+    VcpNames(Vec<(FeatureCode, bool)>),
+    Commands,
+    MccsVersion,
+    Other,
+}
+
+/// A declaration of a feature within a `vcp()` entry.
+struct Feature {
+    /// The declared feature and its values.
+    span: VcpSpan,
+    /// The values in the order they are declared.
+    values: Vec<u8>,
+    /// The features declared alongside this one.
+    siblings: Vec<Span>,
+    /// The index of this feature in `siblings`.
+    index: usize,
+}
+
+impl Entry {
+    fn new(cap: &Cap, span: CapSpan) -> Self {
+        let kind = match cap {
+            Cap::Vcp(vcp) => EntryKind::Vcp(
+                vcp.iter()
+                    .map(|vcp| (vcp.feature, vcp.values().iter().map(|v| v.value).collect()))
+                    .collect(),
+            ),
+            Cap::VcpNames(names) => EntryKind::VcpNames(
+                names
+                    .iter()
+                    .map(|name| (name.feature, name.value_names.is_some()))
+                    .collect(),
+            ),
+            Cap::Commands(..) => EntryKind::Commands,
+            Cap::MccsVersion(..) => EntryKind::MccsVersion,
+            _ => EntryKind::Other,
+        };
+        Entry {
+            binary: matches!(cap, Cap::Edid(..) | Cap::Vdif(..) | Cap::Unknown(Value::Binary { .. })),
+            span,
+            kind,
+        }
+    }
+
+    /// The entry as it appears in `source`.
+    fn value<'i>(&self, source: &'i [u8]) -> Value<'i> {
+        let tag = match &self.span.entry.tag {
+            Some(tag) => str::from_utf8(&source[tag.clone()]).expect("tags were validated"),
+            // a model name without its tag, see `Hack::ModelWithoutTag`
+            None => "model",
+        };
+        let contents = &source[self.span.entry.contents.clone()];
+        match self.binary {
+            true => Value::Binary { tag, data: contents },
+            false => Value::String { tag, value: contents },
+        }
+    }
+
+    /// Moves the entry `offset` bytes further into the capability string.
+    fn shift(&mut self, offset: isize) {
+        let shift = |span: &mut Span| {
+            span.start = span.start.wrapping_add_signed(offset);
+            span.end = span.end.wrapping_add_signed(offset);
+        };
+        let CapSpan { entry, vcp, vcp_names } = &mut self.span;
+        shift(&mut entry.entry);
+        entry.tag.iter_mut().for_each(shift);
+        shift(&mut entry.contents);
+        for vcp in vcp {
+            shift(&mut vcp.span);
+            shift(&mut vcp.feature);
+            for value in &mut vcp.values {
+                shift(&mut value.span);
+                shift(&mut value.value);
+                value.sub_values.iter_mut().for_each(shift);
+            }
+        }
+        for name in vcp_names {
+            shift(&mut name.span);
+            shift(&mut name.feature);
+            name.name.iter_mut().for_each(shift);
+            name.value_names.iter_mut().for_each(shift);
+        }
+    }
+}
+
+impl CapabilityTree {
+    /// Parses a capability string.
+    pub fn parse<C: Into<Vec<u8>>>(capability_string: C) -> Result<Self, ParseError> {
+        let source = capability_string.into();
+        let entries = Cap::parse_entries_spanned(ValueParser::new(&source))
+            .map(|e| e.map(|(cap, span)| Entry::new(&cap, span)))
+            .collect::<Result<_, _>>()?;
+        Ok(CapabilityTree { source, entries })
+    }
+
+    /// The capability string.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.source
+    }
+
+    /// Consumes the tree, returning the capability string.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.source
+    }
+
+    /// The entries of the capability string along with their locations.
+    pub fn entries(&self) -> impl Iterator<Item = (Cap<'_>, CapSpan)> + '_ {
+        let options = ParseOptions::default();
+        self.entries.iter().map(move |entry| {
+            Cap::parse_entry_spanned(entry.value(&self.source), entry.span.entry.clone(), &options)
+                .expect("capability string was validated")
+        })
+    }
+
+    /// The parsed capabilities.
+    pub fn capabilities(&self) -> Capabilities {
+        let mut repeats = Repeats::default();
+        let options = ParseOptions::default();
+        let entries = self.entries.iter().map(|entry| {
+            let value = entry.value(&self.source);
+            let cap = Cap::parse_entry_with(value, &options).expect("capability string was validated");
+            Ok::<_, Infallible>(repeats.entry(cap, value))
+        });
+        capabilities_from_entries(entries).unwrap_or_else(|e| match e {})
+    }
+
+    /// Declares a feature code in the `vcp()` entry.
+    ///
+    /// Returns `false` if the feature was already declared.
+    pub fn add_vcp(&mut self, feature: FeatureCode) -> bool {
+        if !self.features(feature).is_empty() {
+            return false
+        }
+
+        let code = self.hex(feature);
+        let edit = match self.entries.iter().rfind(|e| matches!(e.kind, EntryKind::Vcp(..))) {
+            Some(entry) => match entry.span.vcp.last() {
+                Some(last) => {
+                    let siblings: Vec<_> = entry.span.vcp.iter().map(|vcp| vcp.span.clone()).collect();
+                    let separator = separator(&siblings);
+                    (last.span.end..last.span.end, format!("{separator}{code}"))
+                },
+                None => {
+                    let at = entry.span.entry.contents.start;
+                    (at..at, code)
+                },
+            },
+            None => self.append(format!("vcp({code})")),
+        };
+        self.splice(vec![edit]);
+        true
+    }
+
+    /// Removes a feature code from the `vcp()` and `vcpname()` entries.
+    ///
+    /// Returns `false` if the feature was not declared or named.
+    pub fn remove_vcp(&mut self, feature: FeatureCode) -> bool {
+        let mut edits: Vec<_> = self
+            .features(feature)
+            .into_iter()
+            .map(|vcp| (removal(&vcp.siblings, vcp.index), String::new()))
+            .collect();
+        for entry in &self.entries {
+            if let EntryKind::VcpNames(names) = &entry.kind {
+                let siblings: Vec<_> = entry.span.vcp_names.iter().map(|name| name.span.clone()).collect();
+                edits.extend(
+                    names
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, &(name, _))| name == feature)
+                        .map(|(index, _)| (removal(&siblings, index), String::new())),
+                );
+            }
+        }
+
+        let removed = !edits.is_empty();
+        self.splice(edits);
+        removed
+    }
+
+    /// Adds an allowed value to a feature, declaring the feature if necessary.
+    ///
+    /// Value names from `vcpname()` are positional, so if the feature has
+    /// named values the hex value is inserted as a placeholder name to keep
+    /// the existing names aligned.
+    ///
+    /// Returns `false` if the value was already allowed.
+    pub fn add_vcp_value(&mut self, feature: FeatureCode, value: u8) -> bool {
+        self.add_vcp(feature);
+        let features = self.features(feature);
+        let values: BTreeSet<_> = features.iter().flat_map(|vcp| vcp.values.iter().copied()).collect();
+        if values.contains(&value) {
+            return false
+        }
+
+        let vcp = &features[0];
+        let code = self.hex(value);
+        let value_spans: Vec<_> = vcp.span.values.iter().map(|value| value.span.clone()).collect();
+        let separator = separator(&value_spans);
+        let edit = match vcp.values.iter().position(|&v| v > value) {
+            Some(next) => {
+                let at = value_spans[next].start;
+                (at..at, format!("{code}{separator}"))
+            },
+            None => match value_spans.last() {
+                Some(last) => (last.end..last.end, format!("{separator}{code}")),
+                None => match self.source[vcp.span.feature.end..vcp.span.span.end].contains(&b'(') {
+                    true => (vcp.span.span.end - 1..vcp.span.span.end - 1, code),
+                    false => (vcp.span.feature.end..vcp.span.feature.end, format!("({code})")),
+                },
+            },
+        };
+
+        let index = values.range(..value).count();
+        let mut edits = vec![edit];
+        edits.extend(self.value_names(feature).and_then(|names| {
+            names
+                .get(index)
+                .map(|name| (name.start..name.start, format!("{} ", self.hex(value))))
+        }));
+        self.splice(edits);
+        true
+    }
+
+    /// Removes an allowed value from a feature, along with its name from
+    /// `vcpname()`.
+    ///
+    /// Returns `false` if the value was not allowed.
+    pub fn remove_vcp_value(&mut self, feature: FeatureCode, value: u8) -> bool {
+        let features = self.features(feature);
+        let values: BTreeSet<_> = features.iter().flat_map(|vcp| vcp.values.iter().copied()).collect();
+        let mut edits: Vec<_> = features
+            .iter()
+            .flat_map(|vcp| {
+                let siblings: Vec<_> = vcp.span.values.iter().map(|value| value.span.clone()).collect();
+                vcp.values
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &v)| v == value)
+                    .map(move |(index, _)| (removal(&siblings, index), String::new()))
+            })
+            .collect();
+        if edits.is_empty() {
+            return false
+        }
+
+        let index = values.range(..value).count();
+        if let Some(names) = self.value_names(feature) {
+            if index < names.len() {
+                edits.push((removal(&names, index), String::new()));
+            }
+        }
+        self.splice(edits);
+        true
+    }
+
+    /// Sets the name of a feature in the `vcpname()` entry.
+    pub fn set_vcp_name(&mut self, feature: FeatureCode, name: &str) {
+        let name = Escaped(name).to_string();
+        let mut existing = None;
+        let mut last_entry = None;
+        for entry in &self.entries {
+            if let EntryKind::VcpNames(names) = &entry.kind {
+                for (&(vcp, _), vcp_span) in names.iter().zip(&entry.span.vcp_names) {
+                    if vcp == feature {
+                        existing = Some(vcp_span);
+                    }
+                }
+                last_entry = Some(&entry.span);
+            }
+        }
+
+        let edit = match (existing, last_entry) {
+            (Some(vcp), _) => match &vcp.name {
+                Some(span) => (span.clone(), name),
+                None => {
+                    let bracket = self.source[vcp.feature.end..]
+                        .iter()
+                        .position(|&c| c == b'(')
+                        .expect("vcpname feature has brackets");
+                    let at = vcp.feature.end + bracket + 1;
+                    (at..at, name)
+                },
+            },
+            (None, Some(entry)) => {
+                let at = entry
+                    .vcp_names
+                    .last()
+                    .map(|vcp| vcp.span.end)
+                    .unwrap_or(entry.entry.contents.start);
+                (at..at, format!("{}({name})", self.hex(feature)))
+            },
+            (None, None) => self.append(format!("vcpname({}({name}))", self.hex(feature))),
+        };
+        self.splice(vec![edit]);
+    }
+
+    /// Sets the MCCS version in the `mccs_ver()` entry.
+    pub fn set_mccs_version(&mut self, version: Version) {
+        let mut edits: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.kind == EntryKind::MccsVersion)
+            .map(|entry| {
+                let contents = entry.span.entry.contents.clone();
+                let version = match self.source[contents.clone()].contains(&b'.') {
+                    true => format!("{}.{}", version.major, version.minor),
+                    false => format!("{:02}{:02}", version.major, version.minor),
+                };
+                (contents, version)
+            })
+            .collect();
+        if edits.is_empty() {
+            edits.push(self.append(format!("mccs_ver({}.{})", version.major, version.minor)));
+        }
+        self.splice(edits);
+    }
+
+    /// Each declaration of `feature` in the `vcp()` entries.
+    fn features(&self, feature: FeatureCode) -> Vec<Feature> {
+        let mut features = Vec::new();
+        for entry in &self.entries {
+            if let EntryKind::Vcp(vcp) = &entry.kind {
+                let siblings: Vec<_> = entry.span.vcp.iter().map(|vcp| vcp.span.clone()).collect();
+                features.extend(
+                    vcp.iter()
+                        .zip(&entry.span.vcp)
+                        .enumerate()
+                        .filter(|(_, ((code, _), _))| *code == feature)
+                        .map(|(index, ((_, values), span))| Feature {
+                            span: span.clone(),
+                            values: values.clone(),
+                            siblings: siblings.clone(),
+                            index,
+                        }),
+                );
+            }
+        }
+        features
+    }
+
+    /// The locations of the value names that apply to `feature`.
+    fn value_names(&self, feature: FeatureCode) -> Option<Vec<Span>> {
+        let mut value_names = None;
+        for entry in &self.entries {
+            if let EntryKind::VcpNames(names) = &entry.kind {
+                for (&(vcp, named), span) in names.iter().zip(&entry.span.vcp_names) {
+                    if vcp == feature && named {
+                        value_names = Some(span.value_names.clone());
+                    }
+                }
+            }
+        }
+        value_names
+    }
+
+    /// Formats a byte in the same case as the existing hex values.
+    fn hex(&self, value: u8) -> String {
+        let hex = self
+            .entries
+            .iter()
+            .filter(|entry| matches!(entry.kind, EntryKind::Vcp(..) | EntryKind::Commands))
+            .flat_map(|entry| &self.source[entry.span.entry.contents.clone()]);
+        let (lowercase, uppercase) = hex.fold((false, false), |(lower, upper), c| {
+            (lower || matches!(c, b'a'..=b'f'), upper || matches!(c, b'A'..=b'F'))
+        });
+        match lowercase && !uppercase {
+            true => format!("{value:02x}"),
+            false => format!("{value:02X}"),
+        }
+    }
+
+    /// An edit that adds a new entry after the existing ones.
+    fn append(&self, entry: String) -> (Span, String) {
+        let at = match self.entries.last() {
+            Some(entry) => entry.span.entry.entry.end,
+            None => self.source.iter().take_while(|&&c| c == b'(').count(),
+        };
+        (at..at, entry)
+    }
+
+    /// Applies edits that each either change a single entry or insert a new
+    /// one, parsing only the entries they touch.
+    fn splice(&mut self, mut edits: Vec<(Span, String)>) {
+        edits.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+        for (span, replacement) in edits {
+            let offset = replacement.len() as isize - span.len() as isize;
+            let index = self
+                .entries
+                .partition_point(|entry| entry.span.entry.entry.end <= span.start);
+            let edited = self.entries.get(index).map(|entry| entry.span.entry.entry.clone());
+            let edited = edited.filter(|entry| entry.start <= span.start && span.end < entry.end);
+            let inserted = span.start..span.start + replacement.len();
+            self.source.splice(span, replacement.into_bytes());
+
+            let (following, range) = match edited {
+                Some(ref entry) => (index + 1, entry.start..entry.end.wrapping_add_signed(offset)),
+                None => (index, inserted),
+            };
+            self.entries[following..]
+                .iter_mut()
+                .for_each(|entry| entry.shift(offset));
+            let entry = parse_entry(&self.source, range);
+            match edited {
+                Some(_) => self.entries[index] = entry,
+                None => self.entries.insert(index, entry),
+            }
+        }
+    }
+}
+
+/// Parses the single entry found at `range` within `source`.
+fn parse_entry(source: &[u8], range: Span) -> Entry {
+    let (_, (value, span, _)) =
+        Value::parse_spanned(&source[range.clone()], None, false).expect("edits produce valid entries");
+    let (cap, span) = Cap::parse_entry_spanned(value, span.offset(range.start), &ParseOptions::default())
+        .expect("edits produce valid entries");
+    Entry::new(&cap, span)
+}
+
+/// The range to remove along with `items[index]`, including the whitespace
+/// separating it from its neighbours.
+fn removal(items: &[Span], index: usize) -> Span {
+    let item = &items[index];
+    match (index.checked_sub(1).map(|i| &items[i]), items.get(index + 1)) {
+        (_, Some(next)) => item.start..next.start,
+        (Some(previous), None) => previous.end..item.end,
+        (None, None) => item.clone(),
+    }
+}
+
+/// The separator used between `items`, which defaults to a space.
+fn separator(items: &[Span]) -> &'static str {
+    match items.windows(2).all(|w| w[0].end == w[1].start) && items.len() > 1 {
+        true => "",
+        false => " ",
+    }
+}
+
+#[test]
+fn samples_unedited() {
+    for sample in super::testdata::test_data() {
+        let tree = CapabilityTree::parse(sample).unwrap();
+        assert_eq!(tree.as_bytes(), sample);
+        assert_eq!(tree.capabilities(), super::parse_capabilities(sample).unwrap());
+    }
+}
+
+#[test]
+fn edit_vcp() {
+    let mut tree = CapabilityTree::parse(&b"(prot(monitor)vcp(02 04 14(05 08) 60( 0f 11 ) )mswhql(1))"[..]).unwrap();
+    assert!(!tree.add_vcp(0x04));
+    assert!(tree.add_vcp(0xdc));
+    assert_eq!(
+        tree.as_bytes(),
+        b"(prot(monitor)vcp(02 04 14(05 08) 60( 0f 11 ) dc )mswhql(1))"
+    );
+    assert_eq!(tree, CapabilityTree::parse(tree.as_bytes()).unwrap());
+    assert!(tree.remove_vcp(0x04));
+    assert!(!tree.remove_vcp(0x04));
+    assert_eq!(
+        tree.as_bytes(),
+        b"(prot(monitor)vcp(02 14(05 08) 60( 0f 11 ) dc )mswhql(1))"
+    );
+    assert_eq!(tree, CapabilityTree::parse(tree.as_bytes()).unwrap());
+    assert!(tree.add_vcp_value(0x60, 0x10));
+    assert!(tree.add_vcp_value(0x60, 0x12));
+    assert!(!tree.add_vcp_value(0x60, 0x12));
+    assert!(tree.add_vcp_value(0x02, 0x01));
+    assert_eq!(
+        tree.as_bytes(),
+        b"(prot(monitor)vcp(02(01) 14(05 08) 60( 0f 10 11 12 ) dc )mswhql(1))"
+    );
+    assert_eq!(tree, CapabilityTree::parse(tree.as_bytes()).unwrap());
+    assert!(tree.remove_vcp_value(0x14, 0x05));
+    assert!(tree.remove_vcp_value(0x60, 0x12));
+    assert!(!tree.remove_vcp_value(0x60, 0x12));
+    assert_eq!(
+        tree.as_bytes(),
+        b"(prot(monitor)vcp(02(01) 14(08) 60( 0f 10 11 ) dc )mswhql(1))"
+    );
+    assert_eq!(tree, CapabilityTree::parse(tree.as_bytes()).unwrap());
+
+    let caps = tree.capabilities();
+    assert_eq!(caps.vcp_features.keys().copied().collect::<Vec<_>>(), [
+        0x02, 0x14, 0x60, 0xdc
+    ]);
+    assert_eq!(caps.vcp_features[&0x60].values().copied().collect::<Vec<_>>(), [
+        0x0f, 0x10, 0x11
+    ]);
+}
+
+#[test]
+fn edit_vcp_missing() {
+    let mut tree = CapabilityTree::parse(&b"prot(monitor)type(lcd)model(X)"[..]).unwrap();
+    tree.add_vcp_value(0x14, 0x05);
+    tree.set_vcp_name(0x14, "Color (Preset)");
+    tree.set_mccs_version(Version::new(2, 2));
+    assert_eq!(
+        tree.as_bytes(),
+        &br"prot(monitor)type(lcd)model(X)vcp(14(05))vcpname(14(Color\x20\x28Preset\x29))mccs_ver(2.2)"[..]
+    );
+    assert_eq!(tree, CapabilityTree::parse(tree.as_bytes()).unwrap());
+    assert_eq!(
+        tree.capabilities().vcp_features[&0x14].name.as_deref(),
+        Some("Color (Preset)")
+    );
+}
+
+#[test]
+fn edit_vcpname() {
+    let mut tree = CapabilityTree::parse(
+        &br"vcp(14(010203)448082)vcpname(14((9300 6500 5500))44(Rotate)80(Do\x20this(On Off))82(Fixit))"[..],
+    )
+    .unwrap();
+    tree.set_vcp_name(0x14, "Temp");
+    tree.set_vcp_name(0x44, "Spin");
+    tree.set_vcp_name(0x10, "Brightness");
+    assert!(tree.remove_vcp(0x80));
+    assert!(tree.add_vcp_value(0x14, 0x00));
+    assert!(tree.remove_vcp_value(0x14, 0x02));
+    assert_eq!(
+        tree.as_bytes(),
+        &br"vcp(14(000103)4482)vcpname(14(Temp(00 9300 5500))44(Spin)82(Fixit)10(Brightness))"[..]
+    );
+    assert_eq!(tree, CapabilityTree::parse(tree.as_bytes()).unwrap());
+
+    let caps = tree.capabilities();
+    let values = &caps.vcp_features[&0x14].values;
+    assert_eq!(values[&0x00].as_deref(), Some("00"));
+    assert_eq!(values[&0x01].as_deref(), Some("9300"));
+    assert_eq!(values[&0x03].as_deref(), Some("5500"));
+}
+
+#[test]
+fn edit_mccs_version() {
+    let mut tree = CapabilityTree::parse(&b"(prot(monitor)mccs_ver(0201)mccs_ver(2.1))"[..]).unwrap();
+    tree.set_mccs_version(Version::new(3, 0));
+    assert_eq!(tree.as_bytes(), b"(prot(monitor)mccs_ver(0300)mccs_ver(3.0))");
+}
@@ -1,5 +1,10 @@
 use {
-    super::{bracketed, map_err, trim_spaces, OResult, OResultI, Value},
+    super::{
+        bracketed,
+        error::{expect, Expected, Limit, NomError, ParseError},
+        span::{Span, ValueSpan},
+        trim_spaces, IResult, OResult, OResultI, ParseOptions, Value,
+    },
     nom::{
         branch::alt,
         bytes::complete::{tag, take, take_while1, take_while_m_n},
@@ -7,42 +12,132 @@ use {
             complete::{alphanumeric1, char, space0, u32},
             is_alphanumeric,
         },
-        combinator::{complete, fail, map, map_res, not, peek},
-        error::{self, ErrorKind},
-        sequence::{preceded, tuple},
-        IResult, Parser,
+        combinator::{fail, map, map_res, not, peek},
+        sequence::tuple,
+        Parser,
     },
-    std::{io, str},
+    std::{fmt, str},
 };
 
+/// A workaround applied by the parser to accept a capability string that does
+/// not follow the specification.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Hack {
+    /// The capability string was not enclosed in brackets.
+    MissingBracket,
+    /// The capability string was enclosed in two sets of brackets.
+    ExtraBracket,
+    /// A model name followed `type()` without a `model` tag, as in
+    /// `type(lcd)27UD58cmds(`.
+    ModelWithoutTag,
+    /// A model name followed its tag without brackets, as in
+    /// `modelVG278Hcmds(`.
+    ModelWithoutBracket,
+    /// The `vcp` tag was written in upper case.
+    UpperCaseVcp,
+    /// NUL characters were found between entries.
+    Nul,
+}
+
+impl fmt::Display for Hack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Hack::MissingBracket => "missing outer bracket",
+            Hack::ExtraBracket => "extra outer bracket",
+            Hack::ModelWithoutTag => "model name without tag",
+            Hack::ModelWithoutBracket => "model name without brackets",
+            Hack::UpperCaseVcp => "upper case vcp tag",
+            Hack::Nul => "NUL characters",
+        })
+    }
+}
+
+/// The state of a [`ValueParser`] between entries, independent of its input.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Resume {
+    offset: usize,
+    brackets: Option<usize>,
+    after_type: bool,
+    entries: usize,
+}
+
 #[derive(Clone, Default, Debug, PartialEq, Eq)]
 pub struct ValueParser<'i> {
     pub input: &'i [u8],
     pub brackets: Option<usize>,
-    previous_tag: Option<&'i str>,
+    after_type: bool,
+    capability_string: &'i [u8],
+    hacks: Vec<(Hack, Span)>,
+    span: ValueSpan,
+    entries: usize,
+    options: ParseOptions,
 }
 
 impl<'i> Iterator for ValueParser<'i> {
-    type Item = io::Result<Value<'i>>;
+    type Item = Result<Value<'i>, ParseError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.input.is_empty() {
             return None
         }
 
-        Some(self.nom_result().map_err(map_err))
+        Some(self.nom_result().map_err(|e| self.error(e)))
     }
 }
 
 impl<'i> ValueParser<'i> {
     pub fn new(capability_string: &'i [u8]) -> ValueParser<'i> {
+        Self::with_options(capability_string, Default::default())
+    }
+
+    /// Creates a parser that follows the given options.
+    pub fn with_options(capability_string: &'i [u8], options: ParseOptions) -> ValueParser<'i> {
         Self {
             input: capability_string,
             brackets: None,
-            previous_tag: None,
+            after_type: false,
+            capability_string,
+            hacks: Vec::new(),
+            span: Default::default(),
+            entries: 0,
+            options,
         }
     }
 
+    /// The options this parser follows.
+    pub fn options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    /// The workarounds applied to the entries parsed so far, along with the
+    /// location of the data that required them.
+    pub fn hacks(&self) -> &[(Hack, Span)] {
+        &self.hacks
+    }
+
+    /// Resolves an error relative to the start of the capability string.
+    pub fn error(&self, e: NomError<'i>) -> ParseError {
+        ParseError::new(self.capability_string, e)
+    }
+
+    /// The complete capability string being parsed.
+    pub fn capability_string(&self) -> &'i [u8] {
+        self.capability_string
+    }
+
+    /// Iterates over the entries along with their location in the capability
+    /// string.
+    pub fn spanned(mut self) -> impl Iterator<Item = Result<(Value<'i>, ValueSpan), ParseError>> + 'i {
+        std::iter::from_fn(move || match self.input.is_empty() {
+            true => None,
+            false => Some(
+                self.nom_result()
+                    .map(|value| (value, self.span.clone()))
+                    .map_err(|e| self.error(e)),
+            ),
+        })
+    }
+
     pub fn nom_iter(mut self) -> impl Iterator<Item = OResult<'i, Value<'i>>> + 'i {
         std::iter::from_fn(move || match self.input.is_empty() {
             true => None,
@@ -54,34 +149,120 @@ impl<'i> ValueParser<'i> {
         match self.nom() {
             Ok(o) => Ok(o),
             Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e),
-            Err(nom::Err::Incomplete(_)) => Err(error::Error::new(self.input, ErrorKind::Eof)),
+            Err(nom::Err::Incomplete(_)) => Err(NomError::truncated(self.input, Expected::Char(')'))),
         }
     }
 
     pub fn nom(&mut self) -> OResultI<'i, Value<'i>> {
         self.parse(self.input).map(|(_, e)| e)
     }
+
+    /// Where this parser left off, to be continued by [`resume`](Self::resume)
+    /// once more of the capability string is available.
+    pub(crate) fn suspend(&self) -> Resume {
+        Resume {
+            offset: self.capability_string.len() - self.input.len(),
+            brackets: self.brackets,
+            after_type: self.after_type,
+            entries: self.entries,
+        }
+    }
+
+    /// Continues parsing where a parser of a prefix of `capability_string`
+    /// left off.
+    pub(crate) fn resume(capability_string: &'i [u8], options: ParseOptions, resume: &Resume) -> Self {
+        let mut parser = Self::with_options(capability_string, options);
+        if let Some(brackets) = resume.brackets {
+            // closing brackets may have arrived after the entry preceding them
+            let input = &capability_string[resume.offset..];
+            let (input, brackets_consumed) =
+                caps_suffix(brackets, input, parser.options.skip_nul).unwrap_or((input, 0));
+            parser.input = input;
+            parser.brackets = Some(brackets - brackets_consumed);
+            parser.after_type = resume.after_type;
+            parser.entries = resume.entries;
+        }
+        parser
+    }
+
+    /// Like [`nom`](Self::nom), but treats input that ends before the entry
+    /// does as [`Incomplete`](nom::Err::Incomplete) rather than an error.
+    pub(crate) fn nom_partial(&mut self) -> OResultI<'i, Value<'i>> {
+        match self.nom() {
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) if e.input.is_empty() =>
+                Err(nom::Err::Incomplete(nom::Needed::Unknown)),
+            res => res,
+        }
+    }
 }
 
-impl<'i> Parser<&'i [u8], Value<'i>, error::Error<&'i [u8]>> for ValueParser<'i> {
-    fn parse(&mut self, input: &'i [u8]) -> IResult<&'i [u8], Value<'i>> {
+impl<'i> Parser<&'i [u8], Value<'i>, NomError<'i>> for ValueParser<'i> {
+    fn parse(&mut self, input: &'i [u8]) -> IResult<'i, Value<'i>> {
         let (input, mut brackets) = match self.brackets {
             None => {
-                let (input, brackets) = caps_prefix(input)?;
+                self.capability_string = input;
+                if let Some(max_length) = self.options.max_length.filter(|&max| input.len() > max) {
+                    return Err(nom::Err::Failure(NomError::limit(&input[max_length..], Limit::Length)))
+                }
+                let (input, brackets) = caps_prefix(input, self.options.bracket_tolerance)?;
+                match brackets {
+                    0 => self.hacks.push((Hack::MissingBracket, 0..0)),
+                    1 => (),
+                    _ => self.hacks.push((Hack::ExtraBracket, 1..brackets)),
+                }
                 self.input = input;
                 self.brackets = Some(brackets);
                 (input, brackets)
             },
-            Some(brackets) => (input, brackets),
+            // a resumed parser is given more input with each fragment
+            Some(brackets) => match self
+                .options
+                .max_length
+                .filter(|&max| self.capability_string.len() > max)
+            {
+                Some(max_length) =>
+                    return Err(nom::Err::Failure(NomError::limit(
+                        &self.capability_string[max_length..],
+                        Limit::Length,
+                    ))),
+                None => (input, brackets),
+            },
         };
 
-        let (input, e) = Value::parse_nom(input, self.previous_tag)?;
-        self.previous_tag = Some(e.tag());
+        if self.options.max_entries.is_some_and(|max| self.entries >= max) {
+            return Err(nom::Err::Failure(NomError::limit(input, Limit::Entries)))
+        }
+        let start = self.capability_string.len() - input.len();
+        let previous_tag = self.after_type.then_some("type");
+        let (input, (e, value_span, hack)) = Value::parse_spanned(input, previous_tag, self.options.model_hack)?;
+        if let (Value::String { value, .. }, Some(max_depth)) = (e, self.options.max_depth) {
+            if let Some(depth) = depth_exceeded(value, max_depth) {
+                return Err(nom::Err::Failure(
+                    NomError::limit(depth, Limit::Depth).with_tag(e.tag()),
+                ))
+            }
+        }
+        let value_span = value_span.offset(start);
+        self.after_type = e.tag() == "type";
         self.input = input;
+        self.entries += 1;
 
-        let input = match caps_suffix(brackets, input) {
+        match (hack, &value_span.tag) {
+            (Some(hack), _) => self.hacks.push((hack, value_span.entry.clone())),
+            (None, Some(tag)) if e.tag() == "VCP" && self.options.upper_case_vcp =>
+                self.hacks.push((Hack::UpperCaseVcp, tag.clone())),
+            (None, _) => (),
+        }
+        self.span = value_span;
+
+        let input = match caps_suffix(brackets, input, self.options.skip_nul) {
             Ok((rest, _)) if rest == input => input,
             Ok((input, brackets_consumed)) => {
+                let suffix = &self.input[..self.input.len() - input.len()];
+                if let Some(nul) = suffix.iter().position(|&c| c == 0) {
+                    let start = self.capability_string.len() - self.input.len() + nul;
+                    self.hacks.push((Hack::Nul, start..start + 1));
+                }
                 brackets -= brackets_consumed;
                 self.input = input;
                 self.brackets = Some(brackets);
@@ -90,18 +271,27 @@ impl<'i> Parser<&'i [u8], Value<'i>, error::Error<&'i [u8]>> for ValueParser<'i>
             Err(_) => unreachable!(),
         };
 
+        if input.is_empty() && brackets > 0 && !self.options.bracket_tolerance {
+            return Err(nom::Err::Error(NomError::truncated(input, Expected::Char(')'))))
+        }
+
         Ok((input, e))
     }
 }
 
-fn caps_prefix(i: &[u8]) -> IResult<&[u8], usize> {
+fn caps_prefix(i: &[u8], bracket_tolerance: bool) -> IResult<'_, usize> {
+    if !bracket_tolerance {
+        let (i, _) = char('(')(i)?;
+        return Ok((i, 1))
+    }
+
     // hack around Apple Cinema Display and other displays without any surrounding brackets
     // and displays with too many brackets
     let (i, brackets) = take_while_m_n(0, 2, |c| c == b'(')(i)?;
     Ok((i, brackets.len()))
 }
 
-fn caps_suffix(mut brackets: usize, mut i: &[u8]) -> IResult<&[u8], usize> {
+fn caps_suffix(mut brackets: usize, mut i: &[u8], skip_nul: bool) -> IResult<'_, usize> {
     let mut bracket_count = 0;
     loop {
         i = match i.split_first() {
@@ -113,7 +303,8 @@ fn caps_suffix(mut brackets: usize, mut i: &[u8]) -> IResult<&[u8], usize> {
                 },
                 None => break,
             },
-            Some((&0 | &b' ', i)) => i,
+            Some((&b' ', i)) => i,
+            Some((&0, i)) if skip_nul => i,
             _ => break,
         };
     }
@@ -121,37 +312,81 @@ fn caps_suffix(mut brackets: usize, mut i: &[u8]) -> IResult<&[u8], usize> {
 }
 
 impl<'i> Value<'i> {
-    pub fn parse_nom(input: &'i [u8], previous_tag: Option<&'i str>) -> IResult<&'i [u8], Self> {
+    pub fn parse_nom(input: &'i [u8], previous_tag: Option<&'i str>) -> IResult<'i, Self> {
+        Self::parse_nom_with(input, previous_tag, true)
+    }
+
+    fn parse_nom_with(input: &'i [u8], previous_tag: Option<&'i str>, model_hack: bool) -> IResult<'i, Self> {
+        map(
+            |i| Self::parse_spanned(i, previous_tag, model_hack),
+            |(value, ..)| value,
+        )(input)
+    }
+
+    /// Parses an entry along with its location relative to `input`, and the
+    /// workaround needed to accept it, if any.
+    pub(crate) fn parse_spanned(
+        input: &'i [u8],
+        previous_tag: Option<&'i str>,
+        model_hack: bool,
+    ) -> IResult<'i, (Self, ValueSpan, Option<Hack>)> {
+        let at = |i: &[u8]| input.len() - i.len();
         let (i, _) = space0(input)?;
+        let start = at(i);
         let (i, id) = alt((
             map(
-                preceded(tuple((tag("model"), peek(not(char('('))))), modelhack),
-                |value| Err(Value::String { tag: "model", value }),
+                |i| match model_hack {
+                    true => tuple((map_res(tag("model"), str::from_utf8), peek(not(char('('))), modelhack))(i),
+                    false => fail(i),
+                },
+                |(tag, _, value)| Err((Value::String { tag, value }, Some(start..start + tag.len()))),
             ),
             map(
                 |i| {
-                    if previous_tag == Some("type") {
+                    if model_hack && previous_tag == Some("type") {
                         modelhack(i)
                     } else {
                         fail(i)
                     }
                 },
-                |value| Err(Value::String { tag: "model", value }),
+                |value| Err((Value::String { tag: "model", value }, None)),
             ),
             map(ident, Ok),
         ))(i)?;
-        let (i, cap) = match id {
-            Ok(id) => alt((
-                map(preceded(tag(" bin"), bracketed(binary)), |data| Value::Binary {
-                    tag: id,
-                    data,
-                }),
-                map(bracketed(balancedparens), |value| Value::String { tag: id, value }),
-            ))(i),
-            Err(corrupted_model) => Ok((i, corrupted_model)),
-        }?;
-        let (i, _) = space0(i)?;
-        Ok((i, cap))
+        let (rest, (cap, tag_span, contents, hack)) = match id {
+            Ok(id) => {
+                let tag_span = Some(start..at(i));
+                let (rest, (cap, contents)) = match tag::<_, _, NomError>(" bin")(i) {
+                    Ok((i, _)) => map(bracketed(binary_at), |(offset, data)| {
+                        let contents = at(i) + 1 + offset;
+                        (Value::Binary { tag: id, data }, contents..contents + data.len())
+                    })(i),
+                    // the input ends partway through a binary entry's ` bin(`
+                    Err(_) if !i.is_empty() && b" bin".starts_with(i) =>
+                        Err(nom::Err::Error(NomError::truncated(i, Expected::Char('(')))),
+                    Err(_) => map(bracketed(balancedparens), |value| {
+                        let contents = at(i) + 1;
+                        (Value::String { tag: id, value }, contents..contents + value.len())
+                    })(i),
+                }
+                .map_err(|e| e.map(|e| e.with_tag(id)))?;
+                (rest, (cap, tag_span, contents, None))
+            },
+            Err((corrupted_model, tag_span)) => {
+                let (hack, contents) = match tag_span {
+                    Some(ref tag) => (Hack::ModelWithoutBracket, tag.end..at(i)),
+                    None => (Hack::ModelWithoutTag, start..at(i)),
+                };
+                (i, (corrupted_model, tag_span, contents, Some(hack)))
+            },
+        };
+        let span = ValueSpan {
+            entry: start..at(rest),
+            tag: tag_span,
+            contents,
+        };
+        let (rest, _) = space0(rest)?;
+        Ok((rest, (cap, span, hack)))
     }
 
     pub fn nom_parser() -> ValueParser<'i> {
@@ -159,12 +394,22 @@ impl<'i> Value<'i> {
     }
 }
 
-fn binary(i: &[u8]) -> IResult<&[u8], &[u8]> {
-    let (i, count) = trim_spaces(u32)(i)?;
-    bracketed(take(count))(i)
+pub(crate) fn binary(i: &[u8]) -> IResult<'_, &[u8]> {
+    map(binary_at, |(_, data)| data)(i)
 }
 
-fn modelhack(i: &[u8]) -> IResult<&[u8], &[u8]> {
+/// Binary data along with its offset within `i`.
+fn binary_at(i: &[u8]) -> IResult<'_, (usize, &[u8])> {
+    let (rest, count) = trim_spaces(u32)(i)?;
+    let offset = i.len() - rest.len() + 1;
+    let (rest, data) = bracketed(move |i| match take::<_, _, NomError>(count)(i) {
+        Err(_) => Err(nom::Err::Error(NomError::truncated(i, Expected::Binary))),
+        res => res,
+    })(rest)?;
+    Ok((rest, (offset, data)))
+}
+
+fn modelhack(i: &[u8]) -> IResult<'_, &[u8]> {
     let cmds = b"cmds";
     let (rest, model) = alphanumeric1(i)?;
     if !model.ends_with(cmds) || model == cmds {
@@ -175,15 +420,42 @@ fn modelhack(i: &[u8]) -> IResult<&[u8], &[u8]> {
     Ok((&i[model.len()..], model))
 }
 
-fn ident(i: &[u8]) -> IResult<&[u8], &str> {
-    map_res(take_while1(|c| is_alphanumeric(c) || c == b'_'), str::from_utf8)(i)
+fn ident(i: &[u8]) -> IResult<'_, &str> {
+    expect(
+        Expected::Tag,
+        map_res(take_while1(|c| is_alphanumeric(c) || c == b'_'), str::from_utf8),
+    )(i)
+}
+
+/// The bracket within an entry's contents that nests deeper than `max_depth`,
+/// counting the entry's own brackets.
+fn depth_exceeded(i: &[u8], max_depth: usize) -> Option<&[u8]> {
+    if max_depth == 0 {
+        return Some(i)
+    }
+
+    let mut depth = 1usize;
+    for (x, &c) in i.iter().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            b')' => depth = depth.saturating_sub(1),
+            _ => continue,
+        }
+        if depth > max_depth {
+            return Some(&i[x..])
+        }
+    }
+    None
 }
 
-fn balancedparens(i: &[u8]) -> IResult<&[u8], &[u8]> {
-    complete(balancedparens_incomplete)(i)
+pub(crate) fn balancedparens(i: &[u8]) -> IResult<'_, &[u8]> {
+    match balancedparens_incomplete(i) {
+        Err(nom::Err::Incomplete(_)) => Err(nom::Err::Error(NomError::truncated(i, Expected::Char(')')))),
+        res => res,
+    }
 }
 
-fn balancedparens_incomplete(i: &[u8]) -> IResult<&[u8], &[u8]> {
+fn balancedparens_incomplete(i: &[u8]) -> IResult<'_, &[u8]> {
     use nom::InputTake;
 
     let mut depth = 0usize;
@@ -199,10 +471,7 @@ fn balancedparens_incomplete(i: &[u8]) -> IResult<&[u8], &[u8]> {
             _ => (),
         }
     }
-    match depth {
-        0 => Ok((i, Default::default())),
-        depth => Err(nom::Err::Incomplete(nom::Needed::new(depth))),
-    }
+    Err(nom::Err::Incomplete(nom::Needed::new(depth + 1)))
 }
 
 #[test]
@@ -256,6 +525,64 @@ fn model_hacks() {
     }
 }
 
+#[test]
+fn hacks() {
+    let testdata: [(&[u8], &[_]); 5] = [
+        (b"(prot(monitor)vcp(10))", &[]),
+        (b"prot(monitor)VCP(10)", &[
+            (Hack::MissingBracket, 0..0),
+            (Hack::UpperCaseVcp, 13..16),
+        ]),
+        (b"((type(lcd)ABCdcmds()))\0", &[
+            (Hack::ExtraBracket, 1..2),
+            (Hack::ModelWithoutTag, 11..15),
+            (Hack::Nul, 23..24),
+        ]),
+        (b"(modelABCdcmds())", &[(Hack::ModelWithoutBracket, 1..10)]),
+        (b"(vcp(10) \0\0)", &[(Hack::Nul, 9..10)]),
+    ];
+
+    for (testdata, expected) in testdata {
+        let mut parser = ValueParser::new(testdata);
+        assert!(parser.by_ref().all(|v| v.is_ok()));
+        assert_eq!(parser.hacks(), expected);
+    }
+
+    let options = ParseOptions {
+        upper_case_vcp: false,
+        ..Default::default()
+    };
+    let mut parser = ValueParser::with_options(b"(VCP(10))", options);
+    assert!(parser.by_ref().all(|v| v.is_ok()));
+    assert_eq!(parser.hacks(), []);
+}
+
+#[test]
+fn model_hack_spans() {
+    let testdata: [(&[u8], _); 3] = [
+        (b"type(lcd)ABCdcmds()", ValueSpan {
+            entry: 9..13,
+            tag: None,
+            contents: 9..13,
+        }),
+        (b"type(lcd)modelABCdcmds()", ValueSpan {
+            entry: 9..18,
+            tag: Some(9..14),
+            contents: 14..18,
+        }),
+        (b"type(lcd)model(ABCd)cmds()", ValueSpan {
+            entry: 9..20,
+            tag: Some(9..14),
+            contents: 15..19,
+        }),
+    ];
+
+    for (testdata, expected) in testdata {
+        let spans: Result<Vec<_>, _> = ValueParser::new(testdata).spanned().collect();
+        assert_eq!(spans.unwrap()[1].1, expected);
+    }
+}
+
 #[test]
 fn bin_entries() {
     let testdata = b"edid bin(3(\xff) ))vdif bin(3 (abc))unknown bin(2(ab))";
@@ -277,3 +604,29 @@ fn bin_entries() {
     let entries: Result<Vec<_>, _> = ValueParser::new(testdata).collect();
     assert_eq!(entries.unwrap(), expected);
 }
+
+#[test]
+fn bin_spans() {
+    let testdata = b"edid bin(3(\xff) ))vdif bin( 3 (abc))unknown bin(2(ab))";
+    let spans: Result<Vec<_>, _> = ValueParser::new(testdata)
+        .spanned()
+        .map(|e| e.map(|(_, span)| span))
+        .collect();
+    assert_eq!(spans.unwrap(), [
+        ValueSpan {
+            entry: 0..16,
+            tag: Some(0..4),
+            contents: 11..14,
+        },
+        ValueSpan {
+            entry: 16..34,
+            tag: Some(16..20),
+            contents: 29..32,
+        },
+        ValueSpan {
+            entry: 34..52,
+            tag: Some(34..41),
+            contents: 48..50,
+        },
+    ]);
+}
@@ -0,0 +1,227 @@
+use {
+    nom::error::{ErrorKind, FromExternalError},
+    std::{error, fmt, io},
+};
+
+/// An error encountered while parsing a capability string.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ParseError {
+    /// The byte offset into the capability string where parsing failed.
+    pub offset: usize,
+    /// The entry that was being parsed, if its name was known.
+    pub tag: Option<String>,
+    /// What the parser expected to find at `offset`.
+    pub expected: Expected,
+    /// Whether the input ended early or contained invalid data.
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    /// Resolves an error located within `input` into an offset relative to
+    /// the start of `input`.
+    pub fn new(input: &[u8], e: NomError) -> Self {
+        ParseError {
+            offset: offset(input, e.input),
+            tag: e.tag.map(Into::into),
+            expected: e.expected,
+            kind: e.kind,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::Truncated => write!(f, "capability string ended early, expected {}", self.expected)?,
+            ParseErrorKind::Malformed => write!(f, "expected {}", self.expected)?,
+            ParseErrorKind::Limit(limit) => write!(f, "{limit} limit exceeded")?,
+        }
+        write!(f, " at byte {}", self.offset)?;
+        match &self.tag {
+            Some(tag) => write!(f, " in {tag}()"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(e: ParseError) -> Self {
+        io::Error::new(
+            match e.kind {
+                ParseErrorKind::Truncated => io::ErrorKind::UnexpectedEof,
+                ParseErrorKind::Malformed | ParseErrorKind::Limit(..) => io::ErrorKind::InvalidData,
+            },
+            e,
+        )
+    }
+}
+
+/// Describes the cause of a [`ParseError`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ParseErrorKind {
+    /// The capability string ended in the middle of an entry.
+    Truncated,
+    /// The capability string contained unexpected data.
+    Malformed,
+    /// The capability string exceeded a limit set in
+    /// [`ParseOptions`](crate::ParseOptions).
+    Limit(Limit),
+}
+
+/// A limit set in [`ParseOptions`](crate::ParseOptions).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Limit {
+    /// The length of the capability string.
+    Length,
+    /// The number of entries.
+    Entries,
+    /// The nesting of brackets within an entry.
+    Depth,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Limit::Length => "length",
+            Limit::Entries => "entry count",
+            Limit::Depth => "bracket depth",
+        })
+    }
+}
+
+/// The syntax a parser was looking for when it failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Expected {
+    /// A specific character, such as a bracket.
+    Char(char),
+    /// An entry name, such as `vcp`.
+    Tag,
+    /// A two-digit hexadecimal byte.
+    Hex,
+    /// A decimal number.
+    Decimal,
+    /// UTF-8 encoded text.
+    Utf8,
+    /// An MCCS version, either as `2.1` or `0201`.
+    Version,
+    /// Binary data of the length given in the `bin()` prefix.
+    Binary,
+    /// The end of the entry.
+    End,
+    /// Some other syntax.
+    Other,
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expected::Char(c) => write!(f, "{c:?}"),
+            Expected::Tag => f.write_str("an entry name"),
+            Expected::Hex => f.write_str("a hexadecimal byte"),
+            Expected::Decimal => f.write_str("a decimal number"),
+            Expected::Utf8 => f.write_str("UTF-8 text"),
+            Expected::Version => f.write_str("an MCCS version"),
+            Expected::Binary => f.write_str("binary data"),
+            Expected::End => f.write_str("the end of the entry"),
+            Expected::Other => f.write_str("valid syntax"),
+        }
+    }
+}
+
+/// A parse failure located by its position within the input slice.
+///
+/// This is the error type used by the `nom` parsers, and can be resolved into
+/// a [`ParseError`] given the complete input.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NomError<'i> {
+    /// The remaining input at the point of failure.
+    pub input: &'i [u8],
+    /// The entry that was being parsed, if its name was known.
+    pub tag: Option<&'i str>,
+    /// What the parser expected to find.
+    pub expected: Expected,
+    /// Whether the input ended early or contained invalid data.
+    pub kind: ParseErrorKind,
+}
+
+impl<'i> NomError<'i> {
+    /// Creates a new error at the specified input position.
+    pub fn new(input: &'i [u8], expected: Expected) -> Self {
+        NomError {
+            input,
+            tag: None,
+            expected,
+            kind: match input.is_empty() {
+                true => ParseErrorKind::Truncated,
+                false => ParseErrorKind::Malformed,
+            },
+        }
+    }
+
+    /// Creates a new error signalling that the input ended too early.
+    pub fn truncated(input: &'i [u8], expected: Expected) -> Self {
+        NomError {
+            kind: ParseErrorKind::Truncated,
+            ..Self::new(&input[input.len()..], expected)
+        }
+    }
+
+    /// Creates a new error signalling that a limit was exceeded.
+    pub fn limit(input: &'i [u8], limit: Limit) -> Self {
+        NomError {
+            kind: ParseErrorKind::Limit(limit),
+            ..Self::new(input, Expected::Other)
+        }
+    }
+
+    /// Associates the error with an entry, unless it already has one.
+    pub fn with_tag(self, tag: &'i str) -> Self {
+        NomError {
+            tag: self.tag.or(Some(tag)),
+            ..self
+        }
+    }
+}
+
+impl<'i> nom::error::ParseError<&'i [u8]> for NomError<'i> {
+    fn from_error_kind(input: &'i [u8], kind: ErrorKind) -> Self {
+        Self::new(input, match kind {
+            ErrorKind::Eof if !input.is_empty() => Expected::End,
+            ErrorKind::TakeWhile1 | ErrorKind::AlphaNumeric => Expected::Tag,
+            ErrorKind::Digit => Expected::Decimal,
+            _ => Expected::Other,
+        })
+    }
+
+    fn append(_: &'i [u8], _: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn from_char(input: &'i [u8], c: char) -> Self {
+        Self::new(input, Expected::Char(c))
+    }
+}
+
+impl<'i, E> FromExternalError<&'i [u8], E> for NomError<'i> {
+    fn from_external_error(input: &'i [u8], kind: ErrorKind, _: E) -> Self {
+        nom::error::ParseError::from_error_kind(input, kind)
+    }
+}
+
+/// Overrides what a parser reports as expected when it fails.
+pub(crate) fn expect<'i, O, P>(expected: Expected, mut parser: P) -> impl FnMut(&'i [u8]) -> crate::IResult<'i, O>
+where
+    P: nom::Parser<&'i [u8], O, NomError<'i>>,
+{
+    move |i| parser.parse(i).map_err(|e| e.map(|e| NomError { expected, ..e }))
+}
+
+/// The position of `input` within `origin`.
+pub(crate) fn offset(origin: &[u8], input: &[u8]) -> usize {
+    (input.as_ptr() as usize)
+        .checked_sub(origin.as_ptr() as usize)
+        .filter(|&offset| offset <= origin.len())
+        .unwrap_or_default()
+}
@@ -0,0 +1,225 @@
+use {
+    super::{
+        capabilities_from_entries, entries::Resume, parse_capabilities_with, Cap, ParseError, ParseOptions, Repeats,
+        ValueParser,
+    },
+    mccs::Capabilities,
+};
+
+/// Parses a capability string while it is still being read from the display.
+///
+/// Displays return their capability string over DDC/CI in small fragments.
+/// Each fragment is passed to [`push`](Self::push), which returns the entries
+/// it completed, so a caller can stop reading once it has seen the entries it
+/// needs.
+#[derive(Clone, Debug, Default)]
+pub struct IncrementalParser {
+    buffer: Vec<u8>,
+    options: ParseOptions,
+    resume: Resume,
+    entries: usize,
+    error: Option<ParseError>,
+}
+
+impl IncrementalParser {
+    /// Creates a parser with the default options.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a parser that follows the given options.
+    pub fn with_options(options: ParseOptions) -> Self {
+        IncrementalParser {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Appends a fragment of the capability string, returning each entry
+    /// whose brackets are now balanced.
+    ///
+    /// An error is returned once, after which no further entries are parsed.
+    pub fn push(&mut self, fragment: &[u8]) -> impl Iterator<Item = Result<Cap<'_>, ParseError>> {
+        self.buffer.extend_from_slice(fragment);
+        if self.error.is_some() {
+            return Vec::new().into_iter()
+        }
+
+        let IncrementalParser {
+            buffer,
+            options,
+            resume,
+            entries,
+            error,
+        } = self;
+        let buffer: &[u8] = buffer;
+        let mut values = ValueParser::resume(buffer, options.clone(), resume);
+        let mut caps = Vec::new();
+        while !values.input.is_empty() {
+            let cap = match values.nom_partial() {
+                Err(nom::Err::Incomplete(..)) => break,
+                Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e),
+                Ok(value) => Cap::parse_entry_with(value, options),
+            };
+            match cap {
+                Ok(cap) => {
+                    *resume = values.suspend();
+                    *entries += 1;
+                    caps.push(Ok(cap));
+                },
+                Err(e) => {
+                    let e = values.error(e);
+                    *error = Some(e.clone());
+                    caps.push(Err(e));
+                    break
+                },
+            }
+        }
+        caps.into_iter()
+    }
+
+    /// The capability string received so far.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// The capabilities described by the entries completed so far.
+    pub fn capabilities(&self) -> Capabilities {
+        // the buffer may have outgrown `max_length` after these entries
+        let options = ParseOptions {
+            max_length: None,
+            ..self.options.clone()
+        };
+        let values = ValueParser::with_options(&self.buffer, options);
+        let mut repeats = Repeats::default();
+        let entries = values.nom_iter().take(self.entries).map(|value| {
+            let value = value?;
+            Cap::parse_entry_with(value, &self.options).map(|cap| repeats.entry(cap, value))
+        });
+        capabilities_from_entries(entries).expect("completed entries already parsed")
+    }
+
+    /// Parses the complete capability string once every fragment has been
+    /// pushed.
+    pub fn finish(self) -> Result<Capabilities, ParseError> {
+        match self.error {
+            Some(e) => Err(e),
+            None => parse_capabilities_with(&self.buffer, &self.options),
+        }
+    }
+}
+
+#[test]
+fn incremental_samples() {
+    for sample in super::testdata::test_data() {
+        let mut parser = IncrementalParser::new();
+        let mut tags = Vec::new();
+        let mut error = None;
+        for fragment in sample.chunks(32) {
+            for cap in parser.push(fragment) {
+                match cap {
+                    Ok(cap) => tags.push(cap.tag().to_owned()),
+                    Err(e) => error = Some(e),
+                }
+            }
+        }
+
+        let expected = super::parse_capabilities(sample);
+        match (&expected, error) {
+            (Ok(caps), None) => assert_eq!(&parser.capabilities(), caps),
+            (Err(expected), Some(error)) => assert_eq!(expected, &error),
+            (expected, error) => panic!("{expected:?} != {error:?}"),
+        }
+        assert_eq!(parser.finish(), expected);
+
+        let values = Cap::parse_entries(ValueParser::new(sample))
+            .map_while(Result::ok)
+            .map(|cap| cap.tag().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(tags, values);
+    }
+}
+
+#[test]
+fn incremental_early() {
+    let mut parser = IncrementalParser::new();
+    let tags = |caps: &mut dyn Iterator<Item = Result<Cap, ParseError>>| {
+        caps.map(|cap| cap.unwrap().tag().to_owned()).collect::<Vec<_>>()
+    };
+    assert_eq!(tags(&mut parser.push(b"(prot(monitor)type(lcd)vcp(02 10 14(05 0")), [
+        "prot", "type"
+    ]);
+    assert_eq!(tags(&mut parser.push(b"8))mccs_ver(2")), ["vcp"]);
+    assert_eq!(tags(&mut parser.push(b".1)cmds(01 02")), ["mccs_ver"]);
+
+    let caps = parser.capabilities();
+    assert_eq!(caps.mccs_version, Some(mccs::Version::new(2, 1)));
+    assert_eq!(caps.vcp_features.keys().copied().collect::<Vec<_>>(), [
+        0x02, 0x10, 0x14
+    ]);
+    assert!(caps.commands.is_empty());
+    let len = parser.buffer().len();
+    assert_eq!(parser.finish().unwrap_err().offset, len);
+
+    let mut parser = IncrementalParser::new();
+    let caps = parser.push(b"(prot(monitor)vcp(0G)type(lcd").collect::<Vec<_>>();
+    assert_eq!(caps.len(), 2);
+    assert_eq!(caps[1].as_ref().unwrap_err().tag.as_deref(), Some("vcp"));
+    assert_eq!(parser.push(b")").count(), 0);
+}
+
+#[test]
+fn incremental_splits() {
+    let sample = &b"(prot(monitor)model(X)edid bin(3(abc))vcp(02 10))"[..];
+    let samples = super::testdata::test_data();
+    for sample in samples.iter().map(|s| &s[..]).chain([sample]) {
+        let expected = super::parse_capabilities(sample);
+        let tags = Cap::parse_entries(ValueParser::new(sample))
+            .map_while(Result::ok)
+            .map(|cap| cap.tag().to_owned())
+            .collect::<Vec<_>>();
+        for split in 0..=sample.len() {
+            let mut parser = IncrementalParser::new();
+            let (head, tail) = sample.split_at(split);
+            let mut pushed = Vec::new();
+            for fragment in [head, tail] {
+                pushed.extend(
+                    parser
+                        .push(fragment)
+                        .map_while(Result::ok)
+                        .map(|cap| cap.tag().to_owned()),
+                );
+            }
+            assert_eq!(pushed, tags, "split at {split}");
+            if let Ok(caps) = &expected {
+                assert_eq!(&parser.capabilities(), caps, "split at {split}");
+            }
+            assert_eq!(parser.finish(), expected, "split at {split}");
+        }
+    }
+}
+
+#[test]
+fn incremental_length_limit() {
+    use crate::{Limit, ParseErrorKind};
+
+    let mut parser = IncrementalParser::with_options(ParseOptions {
+        max_length: Some(40),
+        ..Default::default()
+    });
+    assert_eq!(parser.push(b"(prot(monitor)").count(), 1);
+    let mut results = Vec::new();
+    for _ in 0..50 {
+        results.extend(
+            parser
+                .push(b"foo(bar)")
+                .map(|cap| cap.map(|_| ()).map_err(|e| (e.kind, e.offset))),
+        );
+    }
+    let errors: Vec<_> = results.iter().filter_map(|res| res.as_ref().err()).collect();
+    assert_eq!(errors, [&(ParseErrorKind::Limit(Limit::Length), 40)]);
+    assert!(results.len() < 5, "{results:?}");
+    assert_eq!(parser.capabilities().protocol, Some(mccs::Protocol::Monitor));
+    let e = parser.finish().unwrap_err();
+    assert_eq!((e.kind, e.offset), (ParseErrorKind::Limit(Limit::Length), 40));
+}
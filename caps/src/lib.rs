@@ -1,5 +1,5 @@
 #![deny(missing_docs)]
-#![doc(html_root_url = "https://docs.rs/mccs-caps/0.2.0")]
+#![doc(html_root_url = "https://docs.rs/mccs-caps/0.3.0")]
 
 //! MCCS compliant displays will report their supported capabilities in a string
 //! retrieved over DDC/CI. The format of this string is specified in the DDC
@@ -7,38 +7,124 @@
 //! capability string into structured data.
 
 pub use self::{
-    caps::{Cap, Vcp, VcpName, VcpValue},
-    entries::ValueParser,
+    borrowed::{CapabilitiesRef, VcpRef, VcpValueRef},
+    caps::{Cap, Text, Vcp, VcpName, VcpValue},
+    edit::CapabilityTree,
+    entries::{Hack, ValueParser},
+    error::{Expected, Limit, NomError, ParseError, ParseErrorKind},
+    incremental::IncrementalParser,
+    node::Node,
+    options::{Charset, ParseOptions},
+    recover::{parse_capabilities_recover, parse_capabilities_recover_with, Diagnostic},
+    span::{CapSpan, Span, ValueSpan, VcpNameSpan, VcpSpan, VcpValueSpan},
+    validate::{parse_capabilities_strict, validate_capabilities, Violation, ViolationKind},
 };
 use {
     mccs::{Capabilities, UnknownData, UnknownTag, VcpDescriptor, Version},
     nom::Finish,
-    std::{fmt, io, str},
+    std::{fmt, mem, str},
 };
 
 #[cfg(test)]
 mod testdata;
 
+#[cfg(any(test, feature = "corpus"))]
+pub mod corpus;
+
+mod borrowed;
 #[allow(missing_docs)]
 mod caps;
+mod edit;
 #[allow(missing_docs)]
 mod entries;
+mod error;
+mod incremental;
+mod node;
+mod options;
+mod recover;
+mod span;
+mod validate;
 
 /// Parses a MCCS capability string.
-pub fn parse_capabilities<C: AsRef<[u8]>>(capability_string: C) -> io::Result<Capabilities> {
+pub fn parse_capabilities<C: AsRef<[u8]>>(capability_string: C) -> Result<Capabilities, ParseError> {
+    parse_capabilities_with(capability_string, &Default::default())
+}
+
+/// Parses a MCCS capability string following the given options.
+pub fn parse_capabilities_with<C: AsRef<[u8]>>(
+    capability_string: C,
+    options: &ParseOptions,
+) -> Result<Capabilities, ParseError> {
     let capability_string = capability_string.as_ref();
-    let entries = Value::parse_capabilities(capability_string);
+    let entries = ValueParser::with_options(capability_string, options.clone());
 
     // TODO: check for multiple tags of anything only allowed once?
 
+    let parser = entries.clone();
+    let mut repeats = Repeats::default();
+    capabilities_from_entries(entries.nom_iter().map(|value| {
+        value
+            .and_then(|value| Ok(repeats.entry(Cap::parse_entry_with(value, options)?, value)))
+            .map_err(|e| parser.error(e))
+    }))
+}
+
+/// Parses a MCCS capability string, reporting any workarounds that were
+/// needed to accept it.
+///
+/// Each kind of [`Hack`] is listed once, in the order it was first applied.
+pub fn parse_capabilities_with_hacks<C: AsRef<[u8]>>(
+    capability_string: C,
+) -> Result<(Capabilities, Vec<Hack>), ParseError> {
+    let capability_string = capability_string.as_ref();
+    let mut entries = Value::parse_capabilities(capability_string);
+    let mut repeats = Repeats::default();
+    let caps = capabilities_from_entries(entries.by_ref().map(|value| {
+        let value = value?;
+        Cap::parse_entry(value)
+            .map(|cap| repeats.entry(cap, value))
+            .map_err(|e| ParseError::new(capability_string, e))
+    }))?;
+
+    let mut hacks = Vec::new();
+    for &(hack, _) in entries.hacks() {
+        if !hacks.contains(&hack) {
+            hacks.push(hack);
+        }
+    }
+    Ok((caps, hacks))
+}
+
+/// Tracks the entries that may only appear once in a capability string.
+#[derive(Default)]
+pub(crate) struct Repeats {
+    asset_eeprom: bool,
+}
+
+impl Repeats {
+    /// Keeps `cap` as an unknown entry holding the original `value` if it has
+    /// already appeared, rather than letting it replace the earlier one.
+    pub(crate) fn entry<'i>(&mut self, cap: Cap<'i>, value: Value<'i>) -> Cap<'i> {
+        match cap {
+            Cap::AssetEeprom(..) if mem::replace(&mut self.asset_eeprom, true) => Cap::Unknown(value),
+            cap => cap,
+        }
+    }
+}
+
+/// Collects parsed entries into `Capabilities`, stopping at the first error.
+pub(crate) fn capabilities_from_entries<'i, E, I>(entries: I) -> Result<Capabilities, E>
+where
+    I: IntoIterator<Item = Result<Cap<'i>, E>>,
+{
     let mut caps = Capabilities::default();
     let mut vcpnames = Vec::new();
-    for cap in Cap::parse_entries(entries) {
+    for cap in entries {
         match cap? {
-            Cap::Protocol(protocol) => caps.protocol = Some(protocol.into()),
-            Cap::Type(ty) => caps.ty = Some(ty.into()),
+            Cap::Protocol(protocol) => caps.protocol = Some(protocol[..].into()),
+            Cap::Type(ty) => caps.ty = Some(ty[..].into()),
             Cap::Model(model) => caps.model = Some(model.into()),
-            Cap::Commands(ref cmds) => caps.commands = cmds.clone(),
+            Cap::Commands(cmds) => caps.commands = cmds.into_iter().map(Into::into).collect(),
             Cap::Whql(whql) => caps.ms_whql = Some(whql),
             Cap::MccsVersion(major, minor) => caps.mccs_version = Some(Version::new(major, minor)),
             Cap::Vcp(ref vcp) =>
@@ -47,11 +133,13 @@ pub fn parse_capabilities<C: AsRef<[u8]>>(capability_string: C) -> io::Result<Ca
                     ref values,
                 } in vcp
                 {
-                    caps.vcp_features
-                        .entry(*code)
-                        .or_insert_with(|| VcpDescriptor::default())
-                        .values
-                        .extend(values.iter().flat_map(|i| i).map(|v| (v.value, None)))
+                    let desc = caps.vcp_features.entry(*code).or_insert_with(VcpDescriptor::default);
+                    for value in values.iter().flatten() {
+                        desc.values.insert(value.value, None);
+                        if let Some(sub_values) = &value.sub_values {
+                            desc.sub_values.insert(value.value, sub_values.clone());
+                        }
+                    }
                 },
             Cap::VcpNames(v) => vcpnames.extend(v), // wait until after processing vcp() section
             Cap::Unknown(value) => caps.unknown_tags.push(UnknownTag {
@@ -66,6 +154,9 @@ pub fn parse_capabilities<C: AsRef<[u8]>>(capability_string: C) -> io::Result<Ca
             }),
             Cap::Edid(edid) => caps.edid = Some(edid.into()),
             Cap::Vdif(vdif) => caps.vdif.push(vdif.into()),
+            Cap::Window(window) => caps.windows.push(window),
+            Cap::AssetEeprom(size) => caps.asset_eeprom_size = Some(size),
+            Cap::Firmware(version) => caps.firmware.push(version),
         }
     }
 
@@ -93,6 +184,75 @@ pub fn parse_capabilities<C: AsRef<[u8]>>(capability_string: C) -> io::Result<Ca
     Ok(caps)
 }
 
+/// Serializes capabilities into a MCCS capability string.
+///
+/// Parsing the result with [`parse_capabilities`] produces an identical
+/// `Capabilities`. Empty capabilities produce an empty string, as `()` is not
+/// accepted by the parser.
+pub fn write_capabilities(caps: &Capabilities) -> Vec<u8> {
+    let mut capability_string = Vec::new();
+    for cap in Cap::from_capabilities(caps) {
+        if capability_string.is_empty() {
+            capability_string.push(b'(');
+        }
+        cap.write(&mut capability_string).expect("writing to a Vec cannot fail");
+    }
+    if !capability_string.is_empty() {
+        capability_string.push(b')');
+    }
+    capability_string
+}
+
+/// The version of the encoding hashed by [`fingerprint`].
+///
+/// This is increased whenever a release changes the fingerprint of any
+/// capabilities, so fingerprints that are stored should be stored along with
+/// it.
+pub const FINGERPRINT_VERSION: u32 = 1;
+
+/// Computes a fingerprint of capabilities that is stable across platforms,
+/// and across releases that share a [`FINGERPRINT_VERSION`].
+///
+/// This is the 64-bit FNV-1a hash of `FINGERPRINT_VERSION` in little endian,
+/// followed by the capability string written for the
+/// [normalized](Capabilities::normalize) capabilities, so capabilities that
+/// differ only in case or order share a fingerprint.
+pub fn fingerprint(caps: &Capabilities) -> u64 {
+    let mut caps = caps.clone();
+    caps.normalize();
+    FINGERPRINT_VERSION
+        .to_le_bytes()
+        .iter()
+        .chain(&write_capabilities(&caps))
+        .fold(0xcbf29ce484222325, |hash, &c| {
+            (hash ^ c as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Formats capabilities as a MCCS capability string.
+///
+/// Binary and non-UTF-8 data is escaped, so use [`write_capabilities`] to
+/// produce a string that can be sent over DDC/CI or parsed again.
+#[derive(Copy, Clone, Debug)]
+pub struct CapabilitiesDisplay<'a>(pub &'a Capabilities);
+
+impl fmt::Display for CapabilitiesDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut empty = true;
+        for cap in Cap::from_capabilities(self.0) {
+            if empty {
+                f.write_str("(")?;
+                empty = false;
+            }
+            write!(f, "{cap}")?;
+        }
+        match empty {
+            true => Ok(()),
+            false => f.write_str(")"),
+        }
+    }
+}
+
 /// An entry from a capability string
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Value<'i> {
@@ -119,13 +279,16 @@ impl<'i> Value<'i> {
     }
 
     /// Parse a single capability string entry
-    pub fn parse(data: &'i str) -> io::Result<Self> {
+    pub fn parse(data: &'i str) -> Result<Self, ParseError> {
         Self::parse_bytes(data.as_bytes())
     }
 
     /// Parse a single capability string entry
-    pub fn parse_bytes(data: &'i [u8]) -> io::Result<Self> {
-        Self::parse_nom(data, None).finish().map(|(_, v)| v).map_err(map_err)
+    pub fn parse_bytes(data: &'i [u8]) -> Result<Self, ParseError> {
+        Self::parse_nom(data, None)
+            .finish()
+            .map(|(_, v)| v)
+            .map_err(|e| ParseError::new(data, e))
     }
 
     /// The value name
@@ -175,22 +338,53 @@ impl<'i> fmt::Display for Value<'i> {
     }
 }
 
-pub(crate) type OResult<'i, O> = Result<O, nom::error::Error<&'i [u8]>>;
-pub(crate) type OResultI<'i, O> = Result<O, nom::Err<nom::error::Error<&'i [u8]>>>;
+pub(crate) type IResult<'i, O> = nom::IResult<&'i [u8], O, NomError<'i>>;
+pub(crate) type OResult<'i, O> = Result<O, NomError<'i>>;
+pub(crate) type OResultI<'i, O> = Result<O, nom::Err<NomError<'i>>>;
 
-pub(crate) fn map_err(e: nom::error::Error<&[u8]>) -> io::Error {
-    use nom::error::{Error, ErrorKind};
+/// Like `all_consuming(many0(parser))`, but reports why an item failed to
+/// parse rather than where the items ended.
+pub(crate) fn many_all<'i, O, P>(parser: P) -> impl FnMut(&'i [u8]) -> IResult<'i, Vec<O>>
+where
+    P: nom::Parser<&'i [u8], O, NomError<'i>>,
+{
+    fold_all(parser, Vec::new, |mut items, item| {
+        items.push(item);
+        items
+    })
+}
 
-    io::Error::new(
-        match e.code {
-            ErrorKind::Eof | ErrorKind::Complete => io::ErrorKind::UnexpectedEof,
-            _ => io::ErrorKind::InvalidData,
-        },
-        Error {
-            input: e.input.escape_ascii().to_string(),
-            code: e.code,
-        },
-    )
+/// Like [`many_all`], but folds the items rather than collecting them.
+pub(crate) fn fold_all<'i, O, R, P, I, F>(
+    mut parser: P,
+    mut init: I,
+    mut f: F,
+) -> impl FnMut(&'i [u8]) -> IResult<'i, R>
+where
+    P: nom::Parser<&'i [u8], O, NomError<'i>>,
+    I: FnMut() -> R,
+    F: FnMut(R, O) -> R,
+{
+    use nom::{
+        character::complete::space0,
+        error::{ErrorKind, ParseError as _},
+    };
+
+    move |mut i| {
+        let mut acc = init();
+        loop {
+            let (rest, _) = space0(i)?;
+            if rest.is_empty() {
+                return Ok((rest, acc))
+            }
+            let (rest, item) = parser.parse(rest)?;
+            if rest.len() == i.len() {
+                return Err(nom::Err::Error(NomError::from_error_kind(i, ErrorKind::Many0)))
+            }
+            acc = f(acc, item);
+            i = rest;
+        }
+    }
 }
 
 pub(crate) fn trim_spaces<I, O, E, P>(parser: P) -> impl FnMut(I) -> nom::IResult<I, O, E>
@@ -246,3 +440,277 @@ fn samples_high() {
         println!("Caps: {:#?}", caps);
     }
 }
+
+#[test]
+fn samples_hacks() {
+    let samples = testdata::test_data();
+    for sample in &samples {
+        let (caps, _) = parse_capabilities_with_hacks(sample).unwrap();
+        assert_eq!(Ok(caps), parse_capabilities(sample));
+    }
+
+    let hacks = |i: usize| parse_capabilities_with_hacks(samples[i]).unwrap().1;
+    assert_eq!(hacks(0), [Hack::ModelWithoutTag]);
+    assert_eq!(hacks(1), [Hack::Nul]);
+    assert_eq!(hacks(2), []);
+    assert_eq!(hacks(5), [Hack::MissingBracket]);
+}
+
+#[test]
+fn vcp_sub_values() {
+    let caps = parse_capabilities("(vcp(14(05(01 02) 08) 60(0F()))").unwrap();
+    assert_eq!(caps.vcp_features[&0x14].sub_values(0x05), [0x01, 0x02]);
+    assert_eq!(caps.vcp_features[&0x14].sub_values(0x08), []);
+    assert_eq!(caps.vcp_features[&0x60].sub_values.get(&0x0f), Some(&Vec::new()));
+    assert_eq!(parse_capabilities(write_capabilities(&caps)), Ok(caps));
+}
+
+#[test]
+fn samples_spans() {
+    let hex = |sample: &[u8], span: Span| u8::from_str_radix(str::from_utf8(&sample[span]).unwrap(), 16).unwrap();
+    for sample in testdata::test_data() {
+        let entries = Value::parse_capabilities(sample);
+        let spanned = Cap::parse_entries_spanned(entries.clone());
+        for (cap, spanned) in Cap::parse_entries(entries).zip(spanned) {
+            let (cap, (spanned, span)) = (cap.unwrap(), spanned.unwrap());
+            assert_eq!(cap, spanned);
+            if let Some(tag) = span.entry.tag {
+                match &cap {
+                    Cap::Window(window) => assert_eq!(&sample[tag], format!("window{}", window.window).as_bytes()),
+                    cap => assert_eq!(&sample[tag], cap.tag().as_bytes()),
+                }
+            }
+            match cap {
+                Cap::Vcp(vcp) => {
+                    assert_eq!(vcp.len(), span.vcp.len());
+                    for (vcp, span) in vcp.iter().zip(span.vcp) {
+                        assert_eq!(hex(sample, span.feature), vcp.feature);
+                        assert_eq!(vcp.values().len(), span.values.len());
+                        for (value, span) in vcp.values().iter().zip(span.values) {
+                            assert_eq!(hex(sample, span.value), value.value);
+                            let sub_values: Vec<_> =
+                                span.sub_values.into_iter().map(|span| hex(sample, span)).collect();
+                            assert_eq!(value.sub_values(), sub_values);
+                        }
+                    }
+                },
+                Cap::VcpNames(names) => {
+                    assert_eq!(names.len(), span.vcp_names.len());
+                    for (name, span) in names.iter().zip(span.vcp_names) {
+                        assert_eq!(hex(sample, span.feature), name.feature);
+                        assert_eq!(name.value_names().len(), span.value_names.len());
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
+#[test]
+fn parse_errors() {
+    let testdata: [(&[u8], _); 5] = [
+        (b"(prot(monitor)vcp(02 0G 10))", ParseError {
+            offset: 21,
+            tag: Some("vcp".into()),
+            expected: Expected::Hex,
+            kind: ParseErrorKind::Malformed,
+        }),
+        (b"(prot(monitor)vcp(02 04", ParseError {
+            offset: 23,
+            tag: Some("vcp".into()),
+            expected: Expected::Char(')'),
+            kind: ParseErrorKind::Truncated,
+        }),
+        (b"(prot(monitor)mccs_ver(2.x))", ParseError {
+            offset: 25,
+            tag: Some("mccs_ver".into()),
+            expected: Expected::Version,
+            kind: ParseErrorKind::Malformed,
+        }),
+        (b"(prot(monitor)model(\xff))", ParseError {
+            offset: 20,
+            tag: Some("model".into()),
+            expected: Expected::Utf8,
+            kind: ParseErrorKind::Malformed,
+        }),
+        (b"(prot(monitor)edid bin(8(ab))", ParseError {
+            offset: 29,
+            tag: Some("edid".into()),
+            expected: Expected::Binary,
+            kind: ParseErrorKind::Truncated,
+        }),
+    ];
+
+    for (sample, expected) in testdata {
+        let e = parse_capabilities(sample).unwrap_err();
+        println!("{}: {e}", sample.escape_ascii());
+        assert_eq!(e, expected);
+    }
+}
+
+#[test]
+fn samples_roundtrip() {
+    for sample in testdata::test_data() {
+        let caps = parse_capabilities(sample).expect("Failed to parse capabilities");
+        let written = write_capabilities(&caps);
+        println!("Written: {}", written.escape_ascii());
+        let reparsed = parse_capabilities(&written).expect("Failed to parse written capabilities");
+        assert_eq!(caps, reparsed);
+    }
+
+    let samples: [&[u8]; 2] = [
+        "(prot(monitor)window1(type(PIP) foo(a\\x20bé) bar(1(2)))window2(baz(3)))".as_bytes(),
+        b"(window1(foo(M\xfcnchen)))",
+    ];
+    for sample in samples {
+        let caps = parse_capabilities(sample).unwrap();
+        assert_eq!(caps.windows[0].unknown[0].name, "foo");
+        let written = write_capabilities(&caps);
+        assert_eq!(written, sample);
+        assert_eq!(parse_capabilities(&written).unwrap(), caps);
+    }
+}
+
+#[test]
+fn write_escaped_text() {
+    let caps = Capabilities {
+        protocol: Some("x(y".into()),
+        ty: Some("a\\x29b".into()),
+        model: Some("A)B".into()),
+        ..Default::default()
+    };
+    let written = write_capabilities(&caps);
+    assert_eq!(written, &br"(prot(x\x28y)type(a\x5Cx29b)model(A\x29B))"[..]);
+    assert_eq!(parse_capabilities(&written).unwrap(), caps);
+    assert_eq!(Cap::Model("A)B".into()).to_string(), r"model(A\x29B)");
+
+    let caps = parse_capabilities("(model(München (2)))").unwrap();
+    assert_eq!(caps.model.as_deref(), Some("München (2)"));
+    assert_eq!(write_capabilities(&caps), "(model(München \\x282\\x29))".as_bytes());
+}
+
+#[test]
+fn fingerprint_golden() {
+    // Changing any of these values changes stored fingerprints, which needs
+    // an increase of FINGERPRINT_VERSION.
+    assert_eq!(FINGERPRINT_VERSION, 1);
+    let caps = parse_capabilities(
+        &b"(prot(monitor)type(LCD)model(A\\x29B)cmds(01 02 03 F3)mswhql(1)mccs_ver(2.2)vcp(02 10 14(05 08(01 02)) \
+           60(0F 11))vcpname(10(Brightness) 14(Preset(Warm Cool)))edid bin(4(\x00\xff\xff\x00))vdif bin(2(ab))\
+           window1(type(PIP) area(0 0 640 480) max(640 480) min(10 10) window(10) foo(1))asset_eep(64)mpu_ver(V2.00)\
+           foo(bar)baz bin(1(\x01)))"[..],
+    )
+    .unwrap();
+    assert_eq!(caps.windows[0].unknown.len(), 1);
+    assert_eq!(caps.vcp_features[&0x14].sub_values[&0x08], [0x01, 0x02]);
+    assert_eq!(fingerprint(&caps), 0x422c5201ac0f9765);
+}
+
+#[test]
+fn fingerprints() {
+    let caps = parse_capabilities("(prot(monitor)type(LCD)cmds(F3 01)vcp(10 14(05 08))vcp(10)foo(1)bar(2))").unwrap();
+    let mut other = parse_capabilities("(prot(Monitor)type(lcd)cmds(01 f3 01)vcp(14(08 05) 10)bar(2)foo(1))").unwrap();
+    assert_ne!(caps, other);
+    assert_eq!(fingerprint(&caps), fingerprint(&other));
+    other.normalize();
+    let mut caps = caps;
+    caps.normalize();
+    assert_eq!(caps, other);
+    assert_eq!(
+        write_capabilities(&caps),
+        b"(prot(monitor)type(lcd)cmds(01 F3)vcp(10 14(05 08))bar(2)foo(1))"
+    );
+    assert_eq!(fingerprint(&caps), 0x15ed8916ca055c65);
+
+    other.model = Some("X".into());
+    assert_ne!(fingerprint(&caps), fingerprint(&other));
+
+    let caps = parse_capabilities("(window1(type(Overlay) z(1) y(2))window1(type(OVERLAY) y(2) z(1)))").unwrap();
+    assert_eq!(caps.windows.len(), 2);
+    let mut normalized = caps.clone();
+    normalized.normalize();
+    assert_eq!(normalized.windows.len(), 1);
+    assert_eq!(
+        normalized.windows[0].ty,
+        Some(mccs::WindowType::Unknown("overlay".into()))
+    );
+    assert_eq!(fingerprint(&caps), fingerprint(&normalized));
+}
+
+#[test]
+fn parse_options() {
+    use mccs::Protocol;
+
+    let samples = testdata::test_data();
+    let strict = ParseOptions::strict();
+    assert!(parse_capabilities_with(samples[2], &strict).is_ok());
+    assert!(parse_capabilities_with(samples[1], &strict).is_err());
+    assert!(parse_capabilities_with(samples[5], &strict).is_err());
+    let caps = parse_capabilities_with(samples[0], &strict).unwrap();
+    assert_eq!(caps.model, None);
+    assert_eq!(caps.unknown_tags[0].name, "27UD58cmds");
+
+    let caps = parse_capabilities(samples[5]).unwrap();
+    assert_eq!(caps.protocol, None);
+    let case_insensitive = ParseOptions {
+        case_insensitive_tags: true,
+        ..Default::default()
+    };
+    let caps = parse_capabilities_with(samples[5], &case_insensitive).unwrap();
+    assert_eq!(caps.protocol, Some(Protocol::Display));
+    assert!(caps.unknown_tags.iter().all(|tag| tag.name != "Prot"));
+
+    let no_vcp_hack = ParseOptions {
+        upper_case_vcp: false,
+        ..Default::default()
+    };
+    let caps = parse_capabilities_with("(prot(monitor)VCP(10))", &no_vcp_hack).unwrap();
+    assert!(caps.vcp_features.is_empty());
+    assert_eq!(caps.unknown_tags[0].name, "VCP");
+
+    let limit = |options, sample: &[u8]| match parse_capabilities_with(sample, &options) {
+        Err(ParseError {
+            offset,
+            kind: ParseErrorKind::Limit(limit),
+            ..
+        }) => Some((limit, offset)),
+        _ => None,
+    };
+    let limits = |max_length, max_entries, max_depth| ParseOptions {
+        max_length,
+        max_entries,
+        max_depth,
+        ..Default::default()
+    };
+    let nested = b"(prot(monitor)vcp(14(05(01))))";
+    assert_eq!(limit(limits(Some(10), None, None), nested), Some((Limit::Length, 10)));
+    assert_eq!(limit(limits(None, Some(1), None), nested), Some((Limit::Entries, 14)));
+    assert_eq!(limit(limits(None, None, Some(2)), nested), Some((Limit::Depth, 23)));
+    assert_eq!(limit(limits(Some(nested.len()), Some(2), Some(3)), nested), None);
+}
+
+#[test]
+fn commands() {
+    use mccs::Command;
+
+    let caps = parse_capabilities(testdata::test_data()[2]).unwrap();
+    assert_eq!(caps.commands, [
+        Command::VcpRequest,
+        Command::VcpReply,
+        Command::SetVcp,
+        Command::TimingRequest,
+        Command::SaveSettings,
+        Command::CapabilitiesReply,
+        Command::CapabilitiesRequest,
+    ]);
+    assert!(caps.supports_save_settings());
+    assert!(caps.supports_timing_report());
+    assert!(!caps.supports_table_read());
+
+    let caps = parse_capabilities("(cmds(01 E2 E7 9A))").unwrap();
+    assert!(caps.supports_table_read() && caps.supports_table_write());
+    assert_eq!(caps.commands[3], Command::Unknown(0x9a));
+    assert_eq!(caps.commands[3].to_string(), "Unknown command 9A");
+    assert_eq!(write_capabilities(&caps), b"(cmds(01 E2 E7 9A))");
+}
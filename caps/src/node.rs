@@ -0,0 +1,188 @@
+use {
+    super::{
+        bracketed,
+        entries::{balancedparens, binary},
+        error::{Expected, Limit, NomError, ParseError},
+        many_all, IResult,
+    },
+    mccs::{UnknownData, UnknownTag},
+    nom::{
+        bytes::complete::{tag, take_while1},
+        character::complete::space0,
+        combinator::all_consuming,
+        sequence::preceded,
+        Finish,
+    },
+    std::str,
+};
+
+/// Brackets nested deeper than this are rejected rather than decoded.
+const MAX_DEPTH: usize = 64;
+
+/// A generic tree of the nested `name(value name(value))` structures that
+/// ACCESS.bus section 7 allows within an entry.
+///
+/// Useful for inspecting vendor specific entries that are otherwise left as
+/// an [`UnknownTag`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node<'i> {
+    /// A bare value, such as `PIP` or `0F`.
+    Atom(&'i [u8]),
+    /// A named value, such as `type(PIP)`.
+    Tagged {
+        /// The name of the value.
+        tag: &'i str,
+        /// The contents of the brackets.
+        children: Vec<Node<'i>>,
+    },
+    /// Binary data, such as `bin(3(abc))`.
+    Binary(&'i [u8]),
+}
+
+impl<'i> Node<'i> {
+    /// Decodes the contents of an entry.
+    pub fn parse(contents: &'i [u8]) -> Result<Vec<Self>, ParseError> {
+        all_consuming(nodes(MAX_DEPTH))(contents)
+            .finish()
+            .map(|(_, nodes)| nodes)
+            .map_err(|e| ParseError::new(contents, e))
+    }
+
+    /// Decodes an unrecognized entry into a tagged node.
+    ///
+    /// Error offsets are relative to the start of the entry's data.
+    pub fn from_unknown_tag(unknown: &'i UnknownTag) -> Result<Self, ParseError> {
+        let name = &unknown.name[..];
+        let contents = match &unknown.data {
+            UnknownData::String(value) => value.as_bytes(),
+            UnknownData::StringBytes(value) => value,
+            UnknownData::Binary(data) =>
+                return Ok(Node::Tagged {
+                    tag: name,
+                    children: vec![Node::Binary(data)],
+                }),
+        };
+        let children = Self::parse(contents).map_err(|e| ParseError {
+            tag: e.tag.or_else(|| Some(name.into())),
+            ..e
+        })?;
+        Ok(Node::Tagged { tag: name, children })
+    }
+
+    /// The name of a tagged node.
+    pub fn tag(&self) -> Option<&'i str> {
+        match *self {
+            Node::Tagged { tag, .. } => Some(tag),
+            _ => None,
+        }
+    }
+
+    /// The nodes contained within a tagged node.
+    pub fn children(&self) -> &[Node<'i>] {
+        match self {
+            Node::Tagged { children, .. } => children,
+            _ => &[],
+        }
+    }
+
+    /// The first child with the given name.
+    pub fn child(&self, tag: &str) -> Option<&Node<'i>> {
+        self.children().iter().find(|child| child.tag() == Some(tag))
+    }
+}
+
+fn nodes<'i>(depth: usize) -> impl FnMut(&'i [u8]) -> IResult<'i, Vec<Node<'i>>> {
+    move |i| many_all(node(depth))(i)
+}
+
+fn node<'i>(depth: usize) -> impl FnMut(&'i [u8]) -> IResult<'i, Node<'i>> {
+    move |i| {
+        let (rest, atom) = take_while1(|c| !matches!(c, b' ' | b'(' | b')'))(i)
+            .map_err(|e: nom::Err<NomError>| e.map(|e| NomError::new(e.input, Expected::Tag)))?;
+        let (contents, _) = space0(rest)?;
+        let name = || str::from_utf8(atom).map_err(|_| nom::Err::Error(NomError::new(atom, Expected::Tag)));
+        if atom == b"bin" {
+            if let Ok((rest, data)) = bracketed(binary)(contents) {
+                return Ok((rest, Node::Binary(data)))
+            }
+        } else if let Ok((rest, data)) = preceded(tag("bin"), bracketed(binary))(contents) {
+            return Ok((rest, Node::Tagged {
+                tag: name()?,
+                children: vec![Node::Binary(data)],
+            }))
+        }
+        if !contents.starts_with(b"(") {
+            return Ok((rest, Node::Atom(atom)))
+        }
+
+        let tag = name()?;
+        let depth = depth
+            .checked_sub(1)
+            .ok_or_else(|| nom::Err::Failure(NomError::limit(contents, Limit::Depth)))?;
+        let (rest, contents) = bracketed(balancedparens)(contents)?;
+        let (_, children) = nodes(depth)(contents)?;
+        Ok((rest, Node::Tagged { tag, children }))
+    }
+}
+
+#[test]
+fn nested_nodes() {
+    use super::ParseErrorKind;
+
+    let unknown = UnknownTag {
+        name: "vendor".into(),
+        data: UnknownData::String("window1(type (PIP) area(0 0 320 240)) edid bin(3(a())) bin(1(\\)) 1.2".into()),
+    };
+    let node = Node::from_unknown_tag(&unknown).unwrap();
+    assert_eq!(node.tag(), Some("vendor"));
+    assert_eq!(node.children()[1..], [
+        Node::Tagged {
+            tag: "edid",
+            children: vec![Node::Binary(b"a()")],
+        },
+        Node::Binary(b"\\"),
+        Node::Atom(b"1.2"),
+    ]);
+
+    let window = node.child("window1").unwrap();
+    assert_eq!(window.child("type").unwrap().children(), [Node::Atom(b"PIP")]);
+    let area = window.child("area").unwrap().children();
+    assert_eq!(area, ["0", "0", "320", "240"].map(|v| Node::Atom(v.as_bytes())));
+    assert_eq!(window.child("max"), None);
+
+    let binary = UnknownTag {
+        name: "blob".into(),
+        data: UnknownData::Binary(vec![0xff]),
+    };
+    assert_eq!(Node::from_unknown_tag(&binary).unwrap().children(), [Node::Binary(&[
+        0xff
+    ])]);
+
+    let e = Node::from_unknown_tag(&UnknownTag {
+        name: "vendor".into(),
+        data: UnknownData::String("a(b c) (d)".into()),
+    })
+    .unwrap_err();
+    assert_eq!(
+        (e.offset, e.tag.as_deref(), e.expected),
+        (7, Some("vendor"), Expected::Tag)
+    );
+
+    let deep = "a(".repeat(MAX_DEPTH + 1) + &")".repeat(MAX_DEPTH + 1);
+    assert_eq!(
+        Node::parse(deep.as_bytes()).unwrap_err().kind,
+        ParseErrorKind::Limit(Limit::Depth)
+    );
+    assert_eq!(Node::parse(b"").unwrap(), []);
+}
+
+#[test]
+fn sample_nodes() {
+    for sample in super::testdata::test_data() {
+        let caps = super::parse_capabilities(sample).unwrap();
+        for unknown in &caps.unknown_tags {
+            let node = Node::from_unknown_tag(unknown).unwrap();
+            assert_eq!(node.tag(), Some(&unknown.name[..]));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use {
+    super::caps::window_number,
+    std::{borrow::Cow, str},
+};
+
+/// Controls how leniently a capability string is parsed.
+///
+/// The default options accept everything [`parse_capabilities`] always has,
+/// while [`ParseOptions::strict`] disables every workaround.
+///
+/// [`parse_capabilities`]: crate::parse_capabilities
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ParseOptions {
+    /// Accept a model name missing its tag or brackets, as in
+    /// `type(lcd)27UD58cmds(` or `modelVG278Hcmds(`.
+    pub model_hack: bool,
+    /// Accept a capability string missing its outer brackets, or enclosed in
+    /// an extra set of them.
+    pub bracket_tolerance: bool,
+    /// Accept `VCP` in upper case.
+    pub upper_case_vcp: bool,
+    /// Skip NUL characters between entries.
+    pub skip_nul: bool,
+    /// Match the names of all known entries regardless of case, as in
+    /// `Prot(display)`.
+    pub case_insensitive_tags: bool,
+    /// How the text of `model()` and `vcpname()` entries is decoded.
+    pub charset: Charset,
+    /// The maximum length of the capability string, in bytes.
+    pub max_length: Option<usize>,
+    /// The maximum number of entries in the capability string.
+    pub max_entries: Option<usize>,
+    /// The maximum nesting of brackets within an entry, including its own.
+    pub max_depth: Option<usize>,
+}
+
+impl ParseOptions {
+    /// Options that only accept capability strings following the
+    /// specification.
+    pub fn strict() -> Self {
+        ParseOptions {
+            model_hack: false,
+            bracket_tolerance: false,
+            upper_case_vcp: false,
+            skip_nul: false,
+            ..Default::default()
+        }
+    }
+
+    /// The name of the known entry that `tag` refers to, if any.
+    pub(crate) fn known_tag<'i>(&self, tag: &'i str) -> Option<&'i str> {
+        const TAGS: &[&str] = &[
+            "prot",
+            "type",
+            "model",
+            "cmds",
+            "vcp",
+            "vcpname",
+            "mswhql",
+            "mccs_ver",
+            "edid",
+            "vdif",
+            "asset_eep",
+            "mpu_ver",
+            "mpu",
+            "fw",
+            "version",
+        ];
+
+        match tag {
+            _ if TAGS.contains(&tag) => Some(tag),
+            _ if window_number(tag).is_some() && (tag.starts_with("window") || self.case_insensitive_tags) => Some(tag),
+            "VCP" if self.upper_case_vcp => Some("vcp"),
+            _ if self.case_insensitive_tags => TAGS.iter().copied().find(|known| known.eq_ignore_ascii_case(tag)),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            model_hack: true,
+            bracket_tolerance: true,
+            upper_case_vcp: true,
+            skip_nul: true,
+            case_insensitive_tags: false,
+            charset: Default::default(),
+            max_length: None,
+            max_entries: None,
+            max_depth: None,
+        }
+    }
+}
+
+/// The character encoding of text in a capability string.
+///
+/// Escaped bytes such as `\xE9` are decoded along with the rest of the text.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Charset {
+    /// Decode text as UTF-8, falling back to Latin-1 for `vcpname()` text
+    /// that is not valid UTF-8, as earlier releases did.
+    #[default]
+    Compatible,
+    /// Reject text that is not valid UTF-8.
+    Utf8,
+    /// Decode each byte as the character of the same value.
+    Latin1,
+    /// Replace invalid UTF-8 with U+FFFD.
+    LossyUtf8,
+}
+
+impl Charset {
+    /// Decodes `raw` as text in this encoding.
+    pub fn decode(self, raw: &[u8]) -> Result<Cow<'_, str>, str::Utf8Error> {
+        match self {
+            Charset::Compatible | Charset::Utf8 => str::from_utf8(raw).map(Cow::Borrowed),
+            Charset::Latin1 => Ok(match str::from_utf8(raw) {
+                Ok(text) if raw.is_ascii() => Cow::Borrowed(text),
+                _ => Cow::Owned(raw.iter().map(|&c| c as char).collect()),
+            }),
+            Charset::LossyUtf8 => Ok(String::from_utf8_lossy(raw)),
+        }
+    }
+
+    /// Decodes `raw` as the text of a `vcpname()` entry.
+    pub(crate) fn decode_name(self, raw: &[u8]) -> Result<Cow<'_, str>, str::Utf8Error> {
+        match (self, str::from_utf8(raw)) {
+            (Charset::Compatible, Err(_)) => Charset::Latin1.decode(raw),
+            (charset, _) => charset.decode(raw),
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use {
+    super::{
+        capabilities_from_entries,
+        error::{offset, Expected, Limit, NomError},
+        Cap, ParseError, ParseOptions, Repeats, Span, Value, ValueParser,
+    },
+    mccs::Capabilities,
+    nom::character::is_alphanumeric,
+    std::{convert::Infallible, fmt, iter},
+};
+
+/// An error along with the input that was skipped because of it.
+pub(crate) type Skipped<'i> = (NomError<'i>, &'i [u8]);
+
+/// A problem that [`parse_capabilities_recover`] worked around.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    /// The error that interrupted parsing.
+    pub error: ParseError,
+    /// The portion of the capability string that was discarded, which is
+    /// empty if parsing could resume without losing anything.
+    pub skipped: Span,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        match self.skipped.is_empty() {
+            true => Ok(()),
+            false => write!(f, ", skipped bytes {}..{}", self.skipped.start, self.skipped.end),
+        }
+    }
+}
+
+/// Parses a MCCS capability string, skipping over anything malformed.
+///
+/// A malformed item in a `vcp()`, `vcpname()` or `cmds()` entry is dropped
+/// without losing the rest of the entry. Any other error discards input up to
+/// the next recognizable `tag(`, where parsing resumes. Each of these is
+/// reported as a [`Diagnostic`], and an empty list means the result is
+/// identical to that of [`parse_capabilities`](crate::parse_capabilities).
+pub fn parse_capabilities_recover<C: AsRef<[u8]>>(capability_string: C) -> (Capabilities, Vec<Diagnostic>) {
+    parse_capabilities_recover_with(capability_string, &Default::default())
+}
+
+/// Parses a MCCS capability string following the given options, skipping
+/// over anything malformed like [`parse_capabilities_recover`].
+///
+/// Input beyond [`max_length`](ParseOptions::max_length) is discarded, and a
+/// missing opening bracket is accepted even without
+/// [`bracket_tolerance`](ParseOptions::bracket_tolerance). Either is reported
+/// by a single diagnostic.
+pub fn parse_capabilities_recover_with<C: AsRef<[u8]>>(
+    capability_string: C,
+    options: &ParseOptions,
+) -> (Capabilities, Vec<Diagnostic>) {
+    let mut capability_string = capability_string.as_ref();
+    let mut diagnostics = Vec::new();
+
+    // problems with the string as a whole are reported once, before the
+    // entries are recovered from what remains of it
+    let mut options = options.clone();
+    if let Some(max_length) = options.max_length.take().filter(|&max| capability_string.len() > max) {
+        let e = NomError::limit(&capability_string[max_length..], Limit::Length);
+        diagnostics.push(Diagnostic {
+            error: ParseError::new(capability_string, e),
+            skipped: max_length..capability_string.len(),
+        });
+        capability_string = &capability_string[..max_length];
+    }
+    if !options.bracket_tolerance && !capability_string.starts_with(b"(") {
+        let e = NomError::new(capability_string, Expected::Char('('));
+        diagnostics.push(Diagnostic {
+            error: ParseError::new(capability_string, e),
+            skipped: 0..0,
+        });
+        options.bracket_tolerance = true;
+    }
+    let options = &options;
+
+    let mut parser = ValueParser::with_options(capability_string, options.clone());
+    let mut repeats = Repeats::default();
+    let entries = iter::from_fn(|| loop {
+        let input = parser.input;
+        if input.is_empty() {
+            return None
+        }
+
+        let mut diagnostic = |parser: &ValueParser, e, skipped: &[u8]| {
+            let start = offset(capability_string, skipped);
+            diagnostics.push(Diagnostic {
+                error: parser.error(e),
+                skipped: start..start + skipped.len(),
+            })
+        };
+        let (name, tag, mut value) = match parser.nom_result() {
+            Ok(value) => match (options.known_tag(value.tag()), value) {
+                (None, value) => return Some(Ok::<_, Infallible>(Cap::Unknown(value))),
+                (Some(tag), Value::String { tag: name, value }) => (name, tag, value),
+                (Some(tag), Value::Binary { data, .. }) => return Some(Ok(Cap::parse_data(tag, data))),
+            },
+            Err(e) => {
+                let (_, rest) = input.split_at(next_entry(input));
+                diagnostic(&parser, e, &input[..input.len() - rest.len()]);
+                parser.input = rest;
+                continue
+            },
+        };
+
+        // an entry missing its closing bracket swallows those following it
+        if Cap::parse_string_with(tag, value, options.charset).is_err() {
+            let (contents, rest) = value.split_at(next_entry(value));
+            if !rest.is_empty() {
+                let e = NomError::new(rest, Expected::Char(')')).with_tag(tag);
+                diagnostic(&parser, e, &rest[..0]);
+                parser.input = rest;
+                value = contents.trim_ascii_end();
+            }
+        }
+
+        let (cap, skipped) = Cap::parse_string_recover(tag, value, options.charset);
+        for (e, skipped) in skipped {
+            diagnostic(&parser, e, skipped);
+        }
+        if let Some(cap) = cap {
+            return Some(Ok(repeats.entry(cap, Value::String { tag: name, value })))
+        }
+    });
+    let caps = capabilities_from_entries(entries).unwrap_or_else(|e| match e {});
+
+    (caps, diagnostics)
+}
+
+/// Parses a sequence of items like `many_all`, skipping over any that fail to
+/// parse.
+pub(crate) fn many_recover<'i, O, P>(mut parser: P, mut i: &'i [u8]) -> (Vec<O>, Vec<Skipped<'i>>)
+where
+    P: nom::Parser<&'i [u8], O, NomError<'i>>,
+{
+    let mut items = Vec::new();
+    let mut skipped = Vec::new();
+    loop {
+        let start = i.iter().take_while(|&&c| c == b' ').count();
+        let rest = &i[start..];
+        if rest.is_empty() {
+            return (items, skipped)
+        }
+
+        i = match parser.parse(rest) {
+            Ok((i, item)) if i.len() < rest.len() => {
+                items.push(item);
+                i
+            },
+            res => {
+                let e = match res {
+                    Err(nom::Err::Error(e) | nom::Err::Failure(e)) => e,
+                    _ => NomError::new(rest, Expected::Other),
+                };
+                let (item, i) = rest.split_at(item_len(rest));
+                skipped.push((e, item));
+                i
+            },
+        };
+    }
+}
+
+/// The length of the malformed item at the start of `i`: everything up to the
+/// next space or bracket, along with a bracketed group that follows it.
+fn item_len(i: &[u8]) -> usize {
+    let token = i.iter().position(|c| b" ()".contains(c)).unwrap_or(i.len());
+    let group = token + i[token..].iter().take_while(|&&c| c == b' ').count();
+    match i.get(group) {
+        Some(b'(') => {
+            let mut depth = 0usize;
+            for (x, &c) in i[group..].iter().enumerate() {
+                match c {
+                    b'(' => depth += 1,
+                    b')' => depth -= 1,
+                    _ => continue,
+                }
+                if depth == 0 {
+                    return group + x + 1
+                }
+            }
+            i.len()
+        },
+        _ => token.max(1),
+    }
+}
+
+/// The offset of the next entry after the start of `i` that looks like a
+/// `tag(`, or the length of `i` if there is none.
+///
+/// Names consisting only of hex digits are ignored, as these are more likely
+/// to be a feature code such as the `60(` in a `vcp()` entry.
+fn next_entry(i: &[u8]) -> usize {
+    let is_ident = |c: u8| is_alphanumeric(c) || c == b'_';
+    (1..i.len())
+        .find(|&x| {
+            let (name, rest) = i[x..].split_at(i[x..].iter().take_while(|&&c| is_ident(c)).count());
+            !is_ident(i[x - 1])
+                && name.iter().any(|c| !c.is_ascii_hexdigit())
+                && (rest.starts_with(b"(") || rest.starts_with(b" bin("))
+        })
+        .unwrap_or(i.len())
+}
+
+#[test]
+fn samples_recover() {
+    for sample in super::testdata::test_data() {
+        let (caps, diagnostics) = parse_capabilities_recover(sample);
+        assert_eq!(diagnostics, []);
+        assert_eq!(Ok(caps), super::parse_capabilities(sample));
+    }
+}
+
+#[test]
+fn recover_unbalanced() {
+    let samples = super::testdata::test_data();
+    let sample = samples.iter().find(|s| s.windows(4).any(|w| w == b"9EB0")).unwrap();
+    let unbalanced: Vec<u8> = String::from_utf8_lossy(sample)
+        .replacen("60(01 03)", "60(01 03", 1)
+        .into();
+
+    assert!(super::parse_capabilities(&unbalanced).is_err());
+    let (caps, diagnostics) = parse_capabilities_recover(&unbalanced);
+    let offset = unbalanced.windows(5).position(|w| w == b"prot(").unwrap();
+    assert_eq!(diagnostics, [Diagnostic {
+        error: ParseError {
+            offset,
+            tag: Some("vcp".into()),
+            expected: Expected::Char(')'),
+            kind: crate::ParseErrorKind::Malformed,
+        },
+        skipped: offset..offset,
+    }]);
+    assert!(caps.vcp_features.contains_key(&0x60));
+    assert_eq!(caps.model.as_deref(), Some("SA240Y bid"));
+    let commands: Vec<u8> = caps.commands.iter().map(|&c| c.into()).collect();
+    assert_eq!(commands, [0x01, 0x02, 0x03, 0x07, 0x0c, 0xf3]);
+}
+
+#[test]
+fn recover_items() {
+    let testdata = b"(prot(monitor)vcp(02 x 10 14(05 0G 08) 16)cmds(01 Z2)mccs_ver(two)type(lcd)#(34)model(foo))";
+    let (caps, diagnostics) = parse_capabilities_recover(testdata);
+    let skipped: Vec<_> = diagnostics.iter().map(|d| &testdata[d.skipped.clone()]).collect();
+    assert_eq!(skipped, [&b"x"[..], b"(05 0G 08)", b"Z2", b"two", b"#(34)"]);
+    assert_eq!(caps.vcp_features.keys().copied().collect::<Vec<_>>(), [
+        0x02, 0x10, 0x14, 0x16
+    ]);
+    assert_eq!(caps.commands, [mccs::Command::VcpRequest]);
+    assert_eq!(caps.mccs_version, None);
+    assert_eq!(caps.model.as_deref(), Some("foo"));
+    assert!(caps.ty.is_some());
+}
+
+#[test]
+fn recover_with_options() {
+    use super::Charset;
+
+    let testdata = b"(vcp(10 x)vcpname(10(Gr\\xFC\\xDFe))model(M\xfcnchen))";
+    let (caps, diagnostics) = parse_capabilities_recover(testdata);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(caps.vcp_features[&0x10].name.as_deref(), Some("Grüße"));
+    assert_eq!(caps.model, None);
+
+    let options = ParseOptions {
+        charset: Charset::Latin1,
+        ..Default::default()
+    };
+    let (caps, diagnostics) = parse_capabilities_recover_with(testdata, &options);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(caps.model.as_deref(), Some("München"));
+
+    let options = ParseOptions {
+        charset: Charset::Utf8,
+        ..Default::default()
+    };
+    let (caps, _) = parse_capabilities_recover_with(testdata, &options);
+    assert_eq!(caps.vcp_features[&0x10].name, None);
+}
+
+#[test]
+fn recover_whole_string() {
+    use super::ParseErrorKind;
+
+    let testdata = b"prot(monitor)type(lcd)model(X)";
+    let (caps, diagnostics) = parse_capabilities_recover_with(testdata, &ParseOptions::strict());
+    assert_eq!(diagnostics, [Diagnostic {
+        error: ParseError {
+            offset: 0,
+            tag: None,
+            expected: Expected::Char('('),
+            kind: ParseErrorKind::Malformed,
+        },
+        skipped: 0..0,
+    }]);
+    assert_eq!(caps, super::parse_capabilities(testdata).unwrap());
+
+    let testdata = b"(prot(monitor)type(lcd)model(X)vcp(10 12 14)cmds(01 02 03))";
+    let options = ParseOptions {
+        max_length: Some(40),
+        ..Default::default()
+    };
+    let (caps, diagnostics) = parse_capabilities_recover_with(testdata, &options);
+    let errors: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.error.kind, d.error.offset, d.skipped.clone()))
+        .collect();
+    assert_eq!(errors, [
+        (ParseErrorKind::Limit(Limit::Length), 40, 40..testdata.len()),
+        (ParseErrorKind::Truncated, 40, 31..40),
+    ]);
+    assert_eq!(caps.model.as_deref(), Some("X"));
+    assert!(caps.vcp_features.is_empty());
+}
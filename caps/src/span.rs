@@ -0,0 +1,111 @@
+use {
+    super::{IResult, NomError},
+    nom::Parser,
+    std::ops::Range,
+};
+
+/// A byte range within a capability string.
+pub type Span = Range<usize>;
+
+/// The location of an entry within a capability string.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ValueSpan {
+    /// The entire entry, including its name and brackets.
+    pub entry: Span,
+    /// The name of the entry.
+    ///
+    /// This is `None` when the name was inferred by the parser, such as for a
+    /// model name missing its `model(` prefix.
+    pub tag: Option<Span>,
+    /// The contents of the entry, excluding its brackets.
+    ///
+    /// For binary entries this only covers the data itself, not its length
+    /// prefix.
+    pub contents: Span,
+}
+
+impl ValueSpan {
+    /// Moves the span `offset` bytes further into the capability string.
+    pub(crate) fn offset(self, offset: usize) -> Self {
+        let shift = |span: Span| span.start + offset..span.end + offset;
+        ValueSpan {
+            entry: shift(self.entry),
+            tag: self.tag.map(shift),
+            contents: shift(self.contents),
+        }
+    }
+}
+
+/// The location of a parsed entry and its nested items within a capability
+/// string.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CapSpan {
+    /// The location of the entry itself.
+    pub entry: ValueSpan,
+    /// The features of a `vcp()` entry, in order of appearance.
+    pub vcp: Vec<VcpSpan>,
+    /// The features of a `vcpname()` entry, in order of appearance.
+    pub vcp_names: Vec<VcpNameSpan>,
+}
+
+/// The location of a feature declared in a `vcp()` entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VcpSpan {
+    /// The feature code along with its bracketed values.
+    pub span: Span,
+    /// The feature code.
+    pub feature: Span,
+    /// Each of the allowed values.
+    pub values: Vec<VcpValueSpan>,
+}
+
+/// The location of an allowed value within a `vcp()` feature.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VcpValueSpan {
+    /// The value along with its bracketed sub-values.
+    pub span: Span,
+    /// The value.
+    pub value: Span,
+    /// Each of the nested sub-values.
+    pub sub_values: Vec<Span>,
+}
+
+/// The location of a feature described in a `vcpname()` entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VcpNameSpan {
+    /// The feature code along with its bracketed names.
+    pub span: Span,
+    /// The feature code.
+    pub feature: Span,
+    /// The escaped name of the feature.
+    pub name: Option<Span>,
+    /// Each of the escaped value names.
+    pub value_names: Vec<Span>,
+}
+
+/// The input that items are parsed from, along with its position in the
+/// capability string.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Origin<'i> {
+    input: &'i [u8],
+    offset: usize,
+}
+
+impl<'i> Origin<'i> {
+    pub(crate) fn new(input: &'i [u8], offset: usize) -> Self {
+        Origin { input, offset }
+    }
+
+    /// Locates the input consumed by `parser`, which must be a suffix of the
+    /// origin's input.
+    pub(crate) fn located<O, P>(self, mut parser: P) -> impl FnMut(&'i [u8]) -> IResult<'i, (Span, O)>
+    where
+        P: Parser<&'i [u8], O, NomError<'i>>,
+    {
+        move |i| {
+            let (rest, o) = parser.parse(i)?;
+            let at = |i: &[u8]| self.offset + self.input.len() - i.len();
+            Ok((rest, (at(i)..at(rest), o)))
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use {
+    super::{parse_capabilities, Cap, Hack, ParseError, Span, ValueParser},
+    mccs::{Capabilities, FeatureCode},
+    std::{collections::BTreeMap, fmt},
+};
+
+/// A deviation from the capability string format described by the DDC/CI,
+/// MCCS and ACCESS.bus specifications.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Violation {
+    /// The location of the offending data in the capability string.
+    pub span: Span,
+    /// The kind of violation.
+    pub kind: ViolationKind,
+}
+
+/// Describes a [`Violation`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ViolationKind {
+    /// The capability string could not be parsed any further.
+    Malformed(ParseError),
+    /// The capability string is not enclosed in brackets.
+    MissingBracket,
+    /// The capability string is enclosed in more than one set of brackets.
+    ExtraBracket,
+    /// An entry that may only appear once was repeated.
+    DuplicateTag(String),
+    /// A feature code was declared more than once in `vcp()`.
+    DuplicateFeature(FeatureCode),
+    /// A `vcpname()` entry describes a feature that was not declared in
+    /// `vcp()`.
+    UndeclaredFeature(FeatureCode),
+    /// The number of value names in `vcpname()` differs from the number of
+    /// values declared in `vcp()`.
+    ValueNameCount {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The number of values declared in `vcp()`.
+        values: usize,
+        /// The number of value names in `vcpname()`.
+        names: usize,
+    },
+    /// A hex value contained a character that is not a hex digit.
+    InvalidHex,
+    /// The parser needed a workaround to accept the data.
+    ///
+    /// Missing and extra outer brackets are reported as
+    /// [`MissingBracket`](Self::MissingBracket) and
+    /// [`ExtraBracket`](Self::ExtraBracket) instead.
+    Hack(Hack),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ViolationKind::Malformed(e) => return write!(f, "{e}"),
+            ViolationKind::MissingBracket => f.write_str("missing outer bracket")?,
+            ViolationKind::ExtraBracket => f.write_str("extra outer bracket")?,
+            ViolationKind::DuplicateTag(tag) => write!(f, "duplicate {tag}() entry")?,
+            ViolationKind::DuplicateFeature(feature) => write!(f, "feature {feature:02X} declared twice")?,
+            ViolationKind::UndeclaredFeature(feature) => write!(f, "vcpname for undeclared feature {feature:02X}")?,
+            ViolationKind::ValueNameCount { feature, values, names } =>
+                write!(f, "feature {feature:02X} has {values} values but {names} value names")?,
+            ViolationKind::InvalidHex => f.write_str("invalid hex digit")?,
+            ViolationKind::Hack(hack) => write!(f, "{hack}")?,
+        }
+        write!(f, " at byte {}", self.span.start)
+    }
+}
+
+/// Entries that may only appear once in a capability string.
+const SINGLE_TAGS: &[&str] = &["prot", "type", "model", "cmds", "mswhql", "mccs_ver", "edid"];
+
+/// Checks a capability string for every violation of the specification,
+/// including those that [`parse_capabilities`] tolerates.
+pub fn validate_capabilities<C: AsRef<[u8]>>(capability_string: C) -> Vec<Violation> {
+    let capability_string = capability_string.as_ref();
+    let mut violations = Vec::new();
+    let mut violation = |span, kind| violations.push(Violation { span, kind });
+
+    let brackets = capability_string.iter().take_while(|&&c| c == b'(').count().min(2);
+    match brackets {
+        0 => violation(0..0, ViolationKind::MissingBracket),
+        1 => (),
+        _ => violation(1..brackets, ViolationKind::ExtraBracket),
+    }
+
+    let mut tags = Vec::new();
+    let mut features = BTreeMap::new();
+    let mut names = Vec::new();
+    for entry in Cap::parse_entries_spanned(ValueParser::new(capability_string)) {
+        let (cap, span) = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                violation(e.offset..e.offset, ViolationKind::Malformed(e));
+                break
+            },
+        };
+
+        let tag = cap.tag();
+        if SINGLE_TAGS.contains(&tag) {
+            match tags.contains(&tag) {
+                true => violation(span.entry.entry.clone(), ViolationKind::DuplicateTag(tag.into())),
+                false => tags.push(tag),
+            }
+        }
+
+        if matches!(cap, Cap::Vcp(..) | Cap::Commands(..)) {
+            let contents = span.entry.contents.clone();
+            for (i, &c) in capability_string[contents.clone()].iter().enumerate() {
+                if !c.is_ascii_hexdigit() && !b" ()".contains(&c) {
+                    violation(contents.start + i..contents.start + i + 1, ViolationKind::InvalidHex);
+                }
+            }
+        }
+
+        match cap {
+            Cap::Vcp(vcp) =>
+                for (vcp, span) in vcp.into_iter().zip(span.vcp) {
+                    match features.contains_key(&vcp.feature) {
+                        true => violation(span.span, ViolationKind::DuplicateFeature(vcp.feature)),
+                        false => {
+                            features.insert(vcp.feature, vcp.values().len());
+                        },
+                    }
+                },
+            Cap::VcpNames(vcp_names) => names.extend(vcp_names.into_iter().zip(span.vcp_names)),
+            _ => (),
+        }
+    }
+
+    for (name, span) in names {
+        match features.get(&name.feature) {
+            None => violation(span.span, ViolationKind::UndeclaredFeature(name.feature)),
+            Some(&values) => match name.value_names {
+                Some(value_names) if value_names.len() != values =>
+                    violation(span.span, ViolationKind::ValueNameCount {
+                        feature: name.feature,
+                        values,
+                        names: value_names.len(),
+                    }),
+                _ => (),
+            },
+        }
+    }
+
+    let mut parser = ValueParser::new(capability_string);
+    if parser.by_ref().all(|value| value.is_ok()) && parser.brackets.unwrap_or_default() > 0 {
+        let end = capability_string.len();
+        violation(end..end, ViolationKind::MissingBracket);
+    }
+    for (hack, span) in parser.hacks() {
+        if !matches!(hack, Hack::MissingBracket | Hack::ExtraBracket) {
+            violation(span.clone(), ViolationKind::Hack(*hack));
+        }
+    }
+
+    violations
+}
+
+/// Parses a MCCS capability string, rejecting any that violate the
+/// specification.
+pub fn parse_capabilities_strict<C: AsRef<[u8]>>(capability_string: C) -> Result<Capabilities, Vec<Violation>> {
+    let capability_string = capability_string.as_ref();
+    let violations = validate_capabilities(capability_string);
+    match violations.is_empty() {
+        true => parse_capabilities(capability_string).map_err(|e| {
+            vec![Violation {
+                span: e.offset..e.offset,
+                kind: ViolationKind::Malformed(e),
+            }]
+        }),
+        false => Err(violations),
+    }
+}
+
+#[test]
+fn samples_strict() {
+    let samples = super::testdata::test_data();
+    assert_eq!(validate_capabilities(samples[2]), []);
+    assert!(parse_capabilities_strict(samples[2]).is_ok());
+
+    let violations = validate_capabilities(samples[7]);
+    let kinds: Vec<_> = violations.iter().map(|v| &v.kind).collect();
+    assert_eq!(kinds, [
+        &ViolationKind::MissingBracket,
+        &ViolationKind::ValueNameCount {
+            feature: 0x80,
+            values: 0,
+            names: 2,
+        },
+    ]);
+    assert_eq!(&samples[7][violations[1].span.clone()], br"80(Do\x20this(On Off))");
+
+    let violations = validate_capabilities(samples[0]);
+    assert_eq!(violations, [Violation {
+        span: 23..29,
+        kind: ViolationKind::Hack(Hack::ModelWithoutTag),
+    }]);
+
+    for sample in samples {
+        for violation in validate_capabilities(sample) {
+            println!("{}: {violation}", sample.escape_ascii());
+        }
+    }
+}
+
+#[test]
+fn violations() {
+    let testdata =
+        b"((prot(monitor)type(lcd)type(LCD)cmds(01 +2)vcp(10 12(01 02) 10)vcpname(12((a))16(x))mccs_ver(2.1)";
+    let kinds: Vec<_> = validate_capabilities(testdata).into_iter().map(|v| v.kind).collect();
+    assert_eq!(kinds, [
+        ViolationKind::ExtraBracket,
+        ViolationKind::DuplicateTag("type".into()),
+        ViolationKind::InvalidHex,
+        ViolationKind::DuplicateFeature(0x10),
+        ViolationKind::ValueNameCount {
+            feature: 0x12,
+            values: 2,
+            names: 1,
+        },
+        ViolationKind::UndeclaredFeature(0x16),
+        ViolationKind::MissingBracket,
+    ]);
+    assert!(parse_capabilities_strict(testdata).is_err());
+}
@@ -1,5 +1,5 @@
 #![deny(missing_docs)]
-#![doc(html_root_url = "https://docs.rs/mccs-db/0.2.0")]
+#![doc(html_root_url = "https://docs.rs/mccs-db/0.3.0")]
 
 //! Monitor Command Control Set VCP feature code meanings and data
 //! interpretation.
@@ -30,17 +30,14 @@ use {
     std::{collections::BTreeMap, io, mem},
 };
 
-#[cfg(test)]
-#[path = "../../caps/src/testdata.rs"]
-mod testdata;
-
 #[rustfmt::skip::macros(named)]
 mod version_req;
 
 /// Describes how to interpret a table's raw value.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum TableInterpretation {
     /// Generic unparsed data.
+    #[default]
     Generic,
     /// First byte is the code page where `0x00` is the default.
     ///
@@ -50,22 +47,17 @@ pub enum TableInterpretation {
     CodePage,
 }
 
-impl Default for TableInterpretation {
-    fn default() -> Self {
-        TableInterpretation::Generic
-    }
-}
-
 impl TableInterpretation {
     /// Formats a table for user display.
     ///
     /// This can fail if the data is not in the expected format or has an
     /// invalid length.
+    #[allow(clippy::result_unit_err)]
     pub fn format(&self, table: &[u8]) -> Result<String, ()> {
         Ok(match *self {
             TableInterpretation::Generic => format!("{:?}", table),
             TableInterpretation::CodePage =>
-                if let Some(v) = table.get(0) {
+                if let Some(v) = table.first() {
                     format!("{v}")
                 } else {
                     return Err(())
@@ -108,9 +100,10 @@ impl ValueInterpretation {
 }
 
 /// Describes the type of a VCP value and how to interpret it.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum ValueType {
     /// The type of the data is not known
+    #[default]
     Unknown,
     /// The data is a continuous value.
     Continuous {
@@ -132,14 +125,8 @@ pub enum ValueType {
     },
 }
 
-impl Default for ValueType {
-    fn default() -> Self {
-        ValueType::Unknown
-    }
-}
-
 /// The operations allowed on a given VCP feature code.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Access {
     // TODO: bitflags?
     /// The value can only be read from.
@@ -147,15 +134,10 @@ pub enum Access {
     /// The value can only be written to.
     WriteOnly,
     /// The value is both readwritable.
+    #[default]
     ReadWrite,
 }
 
-impl Default for Access {
-    fn default() -> Self {
-        Access::ReadWrite
-    }
-}
-
 /// Describes a VCP feature code's functionality and value format.
 #[derive(Debug, Default, Clone)]
 pub struct Descriptor {
@@ -179,6 +161,9 @@ pub struct Descriptor {
     /// Changing this feature's value may also affect the value of these other
     /// listed features.
     pub interacts_with: Vec<FeatureCode>,
+    /// Nested sub-values that the display declared for some of its allowed
+    /// values.
+    pub sub_values: BTreeMap<u8, Vec<u8>>,
 }
 
 /// Describes all the VCP feature codes supported by an MCCS specification or
@@ -195,7 +180,7 @@ impl Database {
                 continue
             }
 
-            let entry = self.entries.entry(code.code).or_insert_with(|| Descriptor::default());
+            let entry = self.entries.entry(code.code).or_default();
 
             entry.code = code.code;
             if let Some(name) = code.name {
@@ -255,7 +240,7 @@ impl Database {
                                                     .map(move |value| (value, name.clone()))
                                             })
                                             .into_iter()
-                                            .flat_map(|i| i),
+                                            .flatten(),
                                     )
                                 })
                                 .collect(),
@@ -316,7 +301,7 @@ impl Database {
     /// Filter out any feature codes or values that are not supported by the
     /// specified display.
     pub fn apply_capabilities(&mut self, caps: &Capabilities) {
-        let mut entries = mem::replace(&mut self.entries, Default::default());
+        let mut entries = mem::take(&mut self.entries);
         self.entries.extend(
             caps.vcp_features
                 .iter()
@@ -327,12 +312,13 @@ impl Database {
                         }
 
                         if let ValueType::NonContinuous { ref mut values, .. } = mccs.ty {
-                            let mut full = mem::replace(values, Default::default());
+                            let mut full = mem::take(values);
                             values.extend(cap.values.iter().map(|(&value, caps_name)| match full.remove(&value) {
                                 Some(name) => (value, caps_name.clone().or(name)),
                                 None => (value, caps_name.clone()),
                             }));
                         }
+                        mccs.sub_values = cap.sub_values.clone();
 
                         (code, mccs)
                     },
@@ -355,6 +341,7 @@ impl Database {
                             access: Access::ReadWrite,
                             mandatory: false,
                             interacts_with: Vec::new(),
+                            sub_values: cap.sub_values.clone(),
                         };
 
                         (code, desc)
@@ -469,11 +456,41 @@ fn load_database() {
         Version::new(3, 0),
     ] {
         let db = Database::from_version(version);
-        for sample in testdata::test_data() {
-            let caps = mccs_caps::parse_capabilities(sample).expect("Failed to parse capabilities");
+        for sample in mccs_caps::corpus::SAMPLES {
+            let caps = mccs_caps::parse_capabilities(sample.capability_string).expect("Failed to parse capabilities");
             let mut db = db.clone();
             db.apply_capabilities(&caps);
             println!("Intersected: {:#?}", db);
         }
     }
 }
+
+#[test]
+fn apply_sub_values() {
+    let caps = mccs_caps::parse_capabilities("(vcp(14(05(01 02) 08) F0(01(02)))mccs_ver(2.1))").unwrap();
+    let mut db = Database::from_version(&Version::new(2, 1));
+    db.apply_capabilities(&caps);
+    assert_eq!(db.get(0x14).unwrap().sub_values[&0x05], [0x01, 0x02]);
+    assert_eq!(db.get(0xf0).unwrap().sub_values[&0x01], [0x02]);
+}
+
+#[test]
+fn format_table_read() {
+    use mccs::{
+        packet::DisplayMessage,
+        transfer::{Progress, TableReader},
+    };
+
+    let db = Database::from_version(&Version::new(2, 2));
+    let interpretation = match &db.get(0x00).unwrap().ty {
+        &ValueType::Table { interpretation } => interpretation,
+        ty => panic!("unexpected type {ty:?}"),
+    };
+    let mut reader = TableReader::new(0x00);
+    let reply = DisplayMessage::TableReadReply {
+        offset: 0,
+        data: &[0x00, 0x00, 0x00, 0x00],
+    };
+    assert_eq!(reader.receive(&reply.encode().unwrap()), Ok(Progress::Complete));
+    assert_eq!(interpretation.format(&reader.into_data()), Ok("0".into()));
+}
@@ -154,6 +154,7 @@ fn parse_req_expr<V: ReqValue>(i: &str) -> IResult<&str, Req<V>> {
 fn parse_req<V: ReqValue>(i: &str) -> IResult<&str, Req<V>> {
     let (i, _) = space0(i)?;
     let tags = alt((tag("<="), tag("<"), tag(">="), tag(">"), tag("=")));
+    #[allow(clippy::type_complexity)]
     let op: Option<(_, fn(V) -> Req<_>)> = match opt(tags)(i)? {
         (i, Some(op)) => Some((i, match op {
             "<=" => Req::Le,
@@ -172,7 +173,7 @@ fn parse_req<V: ReqValue>(i: &str) -> IResult<&str, Req<V>> {
 }
 
 fn hex_u8(i: &str) -> IResult<&str, u8> {
-    map_res(take_while_m_n(1, 2, |c: char| c.is_digit(16)), |i| {
+    map_res(take_while_m_n(1, 2, |c: char| c.is_ascii_hexdigit()), |i| {
         u8::from_str_radix(i, 16)
     })(i)
 }
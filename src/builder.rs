@@ -0,0 +1,314 @@
+//! A fluent builder for [`Capabilities`].
+//!
+//! Mostly useful for describing synthetic displays in tests, where spelling
+//! out nested maps of VCP descriptors quickly becomes unreadable.
+
+use {
+    super::{Capabilities, Command, FeatureCode, Protocol, Type, UnknownData, UnknownTag, VcpDescriptor, Version},
+    std::{
+        error::Error,
+        fmt::{self, Display, Formatter},
+    },
+};
+
+/// Builds [`Capabilities`] one entry at a time.
+///
+/// Created by [`Capabilities::builder`].
+#[derive(Debug, Default, Clone)]
+pub struct CapabilitiesBuilder {
+    caps: Capabilities,
+    value_names: Vec<(FeatureCode, u8, String)>,
+    sub_values: Vec<(FeatureCode, u8, Vec<u8>)>,
+    errors: Vec<BuildError>,
+}
+
+/// A mistake found while building [`Capabilities`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BuildError {
+    /// A command was added more than once.
+    DuplicateCommand(Command),
+    /// A VCP feature was added more than once.
+    DuplicateFeature(FeatureCode),
+    /// A value was listed more than once for a VCP feature.
+    DuplicateValue {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The value.
+        value: u8,
+    },
+    /// A value was named or given sub-values, but is not listed for its VCP
+    /// feature.
+    ValueMissing {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The value.
+        value: u8,
+    },
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            BuildError::DuplicateCommand(command) => write!(f, "command {:02X} added more than once", command.opcode()),
+            BuildError::DuplicateFeature(feature) => write!(f, "VCP feature {feature:02X} added more than once"),
+            BuildError::DuplicateValue { feature, value } => write!(
+                f,
+                "value {value:02X} listed more than once for VCP feature {feature:02X}"
+            ),
+            BuildError::ValueMissing { feature, value } =>
+                write!(f, "value {value:02X} of VCP feature {feature:02X} is not listed"),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+impl CapabilitiesBuilder {
+    /// Creates a builder for empty capabilities.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the protocol class.
+    pub fn protocol<P: Into<Protocol>>(mut self, protocol: P) -> Self {
+        self.caps.protocol = Some(protocol.into());
+        self
+    }
+
+    /// Sets the display type.
+    pub fn ty<T: Into<Type>>(mut self, ty: T) -> Self {
+        self.caps.ty = Some(ty.into());
+        self
+    }
+
+    /// Sets the model name.
+    pub fn model<S: Into<String>>(mut self, model: S) -> Self {
+        self.caps.model = Some(model.into());
+        self
+    }
+
+    /// Adds supported commands.
+    pub fn commands<I: IntoIterator<Item = Command>>(mut self, commands: I) -> Self {
+        for command in commands {
+            if self.caps.commands.contains(&command) {
+                self.errors.push(BuildError::DuplicateCommand(command));
+            } else {
+                self.caps.commands.push(command);
+            }
+        }
+        self
+    }
+
+    /// Sets the Microsoft WHQL level.
+    pub fn ms_whql(mut self, whql: u8) -> Self {
+        self.caps.ms_whql = Some(whql);
+        self
+    }
+
+    /// Sets the MCCS version.
+    pub fn mccs_version(mut self, major: u8, minor: u8) -> Self {
+        self.caps.mccs_version = Some(Version::new(major, minor));
+        self
+    }
+
+    /// Adds a VCP feature along with its allowed values.
+    ///
+    /// Continuous features have no values listed.
+    pub fn vcp<I: IntoIterator<Item = u8>>(self, feature: FeatureCode, values: I) -> Self {
+        self.feature(feature, None, values)
+    }
+
+    /// Adds a VCP feature with a name and its allowed values.
+    pub fn vcp_named<S: Into<String>, I: IntoIterator<Item = u8>>(
+        self,
+        feature: FeatureCode,
+        name: S,
+        values: I,
+    ) -> Self {
+        self.feature(feature, Some(name.into()), values)
+    }
+
+    /// Names values of a VCP feature.
+    ///
+    /// Each value must also be listed when the feature is added.
+    pub fn value_names<S: Into<String>, I: IntoIterator<Item = (u8, S)>>(
+        mut self,
+        feature: FeatureCode,
+        names: I,
+    ) -> Self {
+        self.value_names
+            .extend(names.into_iter().map(|(value, name)| (feature, value, name.into())));
+        self
+    }
+
+    /// Adds sub-values to a value of a VCP feature.
+    ///
+    /// The value must also be listed when the feature is added.
+    pub fn sub_values<I: IntoIterator<Item = u8>>(mut self, feature: FeatureCode, value: u8, sub_values: I) -> Self {
+        self.sub_values.push((feature, value, sub_values.into_iter().collect()));
+        self
+    }
+
+    /// Adds an unrecognized entry.
+    pub fn unknown_tag<N: Into<String>, S: Into<String>>(mut self, name: N, data: S) -> Self {
+        self.caps.unknown_tags.push(UnknownTag {
+            name: name.into(),
+            data: UnknownData::String(data.into()),
+        });
+        self
+    }
+
+    /// Finishes building, rejecting duplicate commands, features or values,
+    /// and values that were named or given sub-values without being listed.
+    pub fn build(self) -> Result<Capabilities, BuildError> {
+        let (caps, errors) = self.finish();
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(caps),
+        }
+    }
+
+    /// Finishes building without validation.
+    ///
+    /// Duplicate entries are merged, and values that were named or given
+    /// sub-values are listed even if they were not added.
+    pub fn build_unchecked(self) -> Capabilities {
+        self.finish().0
+    }
+
+    fn feature<I: IntoIterator<Item = u8>>(mut self, feature: FeatureCode, name: Option<String>, values: I) -> Self {
+        if self.caps.vcp_features.contains_key(&feature) {
+            self.errors.push(BuildError::DuplicateFeature(feature));
+        }
+        let desc = self.caps.vcp_features.entry(feature).or_default();
+        if name.is_some() {
+            desc.name = name;
+        }
+        for value in values {
+            if desc.values.insert(value, None).is_some() {
+                self.errors.push(BuildError::DuplicateValue { feature, value });
+            }
+        }
+        self
+    }
+
+    fn finish(self) -> (Capabilities, Vec<BuildError>) {
+        let CapabilitiesBuilder {
+            mut caps,
+            value_names,
+            sub_values,
+            mut errors,
+        } = self;
+        for (feature, value, name) in value_names {
+            listed(&mut caps, &mut errors, feature, value)
+                .values
+                .insert(value, Some(name));
+        }
+        for (feature, value, sub_values) in sub_values {
+            listed(&mut caps, &mut errors, feature, value)
+                .sub_values
+                .entry(value)
+                .or_default()
+                .extend(sub_values);
+        }
+        (caps, errors)
+    }
+}
+
+/// The descriptor of a feature whose value is expected to be listed.
+fn listed<'a>(
+    caps: &'a mut Capabilities,
+    errors: &mut Vec<BuildError>,
+    feature: FeatureCode,
+    value: u8,
+) -> &'a mut VcpDescriptor {
+    let desc = caps.vcp_features.entry(feature).or_default();
+    desc.values.entry(value).or_insert_with(|| {
+        errors.push(BuildError::ValueMissing { feature, value });
+        None
+    });
+    desc
+}
+
+#[test]
+fn build_capabilities() {
+    let caps = Capabilities::builder()
+        .protocol("monitor")
+        .ty("lcd")
+        .model("U3011")
+        .commands([Command::VcpRequest, Command::SetVcp])
+        .mccs_version(2, 1)
+        .vcp(0x10, [])
+        .vcp(0x60, [0x0f, 0x11])
+        .vcp_named(0x14, "Color Preset", [0x05, 0x08])
+        .value_names(0x14, [(0x05, "6500K"), (0x08, "9300K")])
+        .sub_values(0x14, 0x05, [0x01, 0x02])
+        .build()
+        .unwrap();
+
+    let mut expected = Capabilities {
+        protocol: Some(Protocol::Monitor),
+        ty: Some(Type::Lcd),
+        model: Some("U3011".into()),
+        commands: vec![Command::VcpRequest, Command::SetVcp],
+        mccs_version: Some(Version::new(2, 1)),
+        ..Default::default()
+    };
+    expected.vcp_features.insert(0x10, Default::default());
+    expected.vcp_features.insert(0x60, VcpDescriptor {
+        values: [(0x0f, None), (0x11, None)].into_iter().collect(),
+        ..Default::default()
+    });
+    expected.vcp_features.insert(0x14, VcpDescriptor {
+        name: Some("Color Preset".into()),
+        values: [(0x05, Some("6500K".into())), (0x08, Some("9300K".into()))]
+            .into_iter()
+            .collect(),
+        sub_values: [(0x05, vec![0x01, 0x02])].into_iter().collect(),
+    });
+    assert_eq!(caps, expected);
+
+    let builder = Capabilities::builder().vcp(0x60, [0x0f]).vcp(0x60, [0x11]);
+    assert_eq!(builder.clone().build(), Err(BuildError::DuplicateFeature(0x60)));
+    assert_eq!(builder.build_unchecked().vcp_features[&0x60].values().count(), 2);
+
+    let builder = Capabilities::builder()
+        .vcp(0x14, [0x05])
+        .value_names(0x14, [(0x08, "9300K")]);
+    assert_eq!(
+        builder.clone().build(),
+        Err(BuildError::ValueMissing {
+            feature: 0x14,
+            value: 0x08
+        })
+    );
+    assert_eq!(
+        builder.build_unchecked().vcp_features[&0x14].values[&0x08].as_deref(),
+        Some("9300K")
+    );
+
+    assert_eq!(
+        Capabilities::builder()
+            .sub_values(0x14, 0x05, [0x01])
+            .vcp(0x14, [])
+            .build(),
+        Err(BuildError::ValueMissing {
+            feature: 0x14,
+            value: 0x05
+        })
+    );
+    assert_eq!(
+        Capabilities::builder().vcp(0x60, [0x0f, 0x0f]).build(),
+        Err(BuildError::DuplicateValue {
+            feature: 0x60,
+            value: 0x0f
+        })
+    );
+    assert_eq!(
+        Capabilities::builder()
+            .commands([Command::SetVcp])
+            .commands([Command::SetVcp])
+            .build(),
+        Err(BuildError::DuplicateCommand(Command::SetVcp))
+    );
+}
@@ -0,0 +1,631 @@
+//! Differences between two sets of capabilities.
+//!
+//! Useful for finding out what a firmware update changed, by comparing the
+//! capabilities a display reported before and after it.
+
+use {
+    super::{
+        Capabilities, Command, EdidData, FeatureCode, FirmwareVersion, Protocol, Type, UnknownData, UnknownTag,
+        VdifData, Version, WindowCapability,
+    },
+    std::{
+        collections::BTreeSet,
+        fmt::{self, Display, Formatter},
+    },
+};
+
+/// The changes between two sets of capabilities.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Diff {
+    /// Each change, ordered by the part of the capabilities it affects.
+    pub changes: Vec<Change>,
+}
+
+/// A single difference between two sets of capabilities.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Change {
+    /// The protocol class changed.
+    Protocol {
+        /// The old protocol.
+        from: Option<Protocol>,
+        /// The new protocol.
+        to: Option<Protocol>,
+    },
+    /// The display type changed.
+    Type {
+        /// The old type.
+        from: Option<Type>,
+        /// The new type.
+        to: Option<Type>,
+    },
+    /// The model name changed.
+    Model {
+        /// The old model name.
+        from: Option<String>,
+        /// The new model name.
+        to: Option<String>,
+    },
+    /// The MCCS version changed.
+    MccsVersion {
+        /// The old version.
+        from: Option<Version>,
+        /// The new version.
+        to: Option<Version>,
+    },
+    /// The Microsoft WHQL level changed.
+    Whql {
+        /// The old level.
+        from: Option<u8>,
+        /// The new level.
+        to: Option<u8>,
+    },
+    /// A command is now supported.
+    CommandAdded(Command),
+    /// A command is no longer supported.
+    CommandRemoved(Command),
+    /// A VCP feature is now supported.
+    FeatureAdded(FeatureCode),
+    /// A VCP feature is no longer supported.
+    FeatureRemoved(FeatureCode),
+    /// A VCP feature was given a different name.
+    FeatureRenamed {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The old name.
+        from: Option<String>,
+        /// The new name.
+        to: Option<String>,
+    },
+    /// A value is now allowed for a VCP feature.
+    ValueAdded {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The value.
+        value: u8,
+    },
+    /// A value is no longer allowed for a VCP feature.
+    ValueRemoved {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The value.
+        value: u8,
+    },
+    /// A value of a VCP feature was given a different name.
+    ValueRenamed {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The value.
+        value: u8,
+        /// The old name.
+        from: Option<String>,
+        /// The new name.
+        to: Option<String>,
+    },
+    /// The sub-values declared for a value of a VCP feature changed.
+    SubValuesChanged {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The value.
+        value: u8,
+        /// The old sub-values.
+        from: Vec<u8>,
+        /// The new sub-values.
+        to: Vec<u8>,
+    },
+    /// The EDID reported in the capability string changed.
+    Edid {
+        /// The old EDID.
+        from: Option<EdidData>,
+        /// The new EDID.
+        to: Option<EdidData>,
+    },
+    /// A VDIF block is now reported.
+    VdifAdded(VdifData),
+    /// A VDIF block is no longer reported.
+    VdifRemoved(VdifData),
+    /// A window is now reported.
+    WindowAdded(WindowCapability),
+    /// A window is no longer reported.
+    WindowRemoved(WindowCapability),
+    /// The size of the asset tag EEPROM changed.
+    AssetEepromSize {
+        /// The old size.
+        from: Option<u16>,
+        /// The new size.
+        to: Option<u16>,
+    },
+    /// A firmware version is now reported.
+    FirmwareAdded(FirmwareVersion),
+    /// A firmware version is no longer reported.
+    FirmwareRemoved(FirmwareVersion),
+    /// An unrecognized entry is now reported.
+    UnknownTagAdded(UnknownTag),
+    /// An unrecognized entry is no longer reported.
+    UnknownTagRemoved(UnknownTag),
+}
+
+impl Diff {
+    /// Compares the `old` capabilities with the `new` ones.
+    pub fn new(old: &Capabilities, new: &Capabilities) -> Self {
+        let mut changes = Vec::new();
+        if old.protocol != new.protocol {
+            changes.push(Change::Protocol {
+                from: old.protocol.clone(),
+                to: new.protocol.clone(),
+            });
+        }
+        if old.ty != new.ty {
+            changes.push(Change::Type {
+                from: old.ty.clone(),
+                to: new.ty.clone(),
+            });
+        }
+        if old.model != new.model {
+            changes.push(Change::Model {
+                from: old.model.clone(),
+                to: new.model.clone(),
+            });
+        }
+        if old.mccs_version != new.mccs_version {
+            changes.push(Change::MccsVersion {
+                from: old.mccs_version,
+                to: new.mccs_version,
+            });
+        }
+        if old.ms_whql != new.ms_whql {
+            changes.push(Change::Whql {
+                from: old.ms_whql,
+                to: new.ms_whql,
+            });
+        }
+
+        changes.extend(removed(&old.commands, &new.commands).map(|&c| Change::CommandRemoved(c)));
+        changes.extend(removed(&new.commands, &old.commands).map(|&c| Change::CommandAdded(c)));
+
+        let features: BTreeSet<_> = old.vcp_features.keys().chain(new.vcp_features.keys()).collect();
+        for &feature in features {
+            let (old, new) = match (old.vcp_features.get(&feature), new.vcp_features.get(&feature)) {
+                (Some(old), Some(new)) => (old, new),
+                (Some(_), None) => {
+                    changes.push(Change::FeatureRemoved(feature));
+                    continue
+                },
+                (None, _) => {
+                    changes.push(Change::FeatureAdded(feature));
+                    continue
+                },
+            };
+
+            if old.name != new.name {
+                changes.push(Change::FeatureRenamed {
+                    feature,
+                    from: old.name.clone(),
+                    to: new.name.clone(),
+                });
+            }
+            let values: BTreeSet<_> = old.values.keys().chain(new.values.keys()).collect();
+            for &value in values {
+                changes.push(match (old.values.get(&value), new.values.get(&value)) {
+                    (Some(from), Some(to)) if from != to => Change::ValueRenamed {
+                        feature,
+                        value,
+                        from: from.clone(),
+                        to: to.clone(),
+                    },
+                    (Some(_), None) => Change::ValueRemoved { feature, value },
+                    (None, _) => Change::ValueAdded { feature, value },
+                    (Some(_), Some(_)) => continue,
+                });
+            }
+            let values: BTreeSet<_> = old.sub_values.keys().chain(new.sub_values.keys()).collect();
+            for &value in values {
+                let (from, to) = (old.sub_values(value), new.sub_values(value));
+                if from != to {
+                    changes.push(Change::SubValuesChanged {
+                        feature,
+                        value,
+                        from: from.into(),
+                        to: to.into(),
+                    });
+                }
+            }
+        }
+
+        if old.edid != new.edid {
+            changes.push(Change::Edid {
+                from: old.edid.clone(),
+                to: new.edid.clone(),
+            });
+        }
+        changes.extend(removed(&old.vdif, &new.vdif).cloned().map(Change::VdifRemoved));
+        changes.extend(removed(&new.vdif, &old.vdif).cloned().map(Change::VdifAdded));
+        changes.extend(removed(&old.windows, &new.windows).cloned().map(Change::WindowRemoved));
+        changes.extend(removed(&new.windows, &old.windows).cloned().map(Change::WindowAdded));
+        if old.asset_eeprom_size != new.asset_eeprom_size {
+            changes.push(Change::AssetEepromSize {
+                from: old.asset_eeprom_size,
+                to: new.asset_eeprom_size,
+            });
+        }
+
+        changes.extend(
+            removed(&old.firmware, &new.firmware)
+                .cloned()
+                .map(Change::FirmwareRemoved),
+        );
+        changes.extend(
+            removed(&new.firmware, &old.firmware)
+                .cloned()
+                .map(Change::FirmwareAdded),
+        );
+        changes.extend(
+            removed(&old.unknown_tags, &new.unknown_tags)
+                .cloned()
+                .map(Change::UnknownTagRemoved),
+        );
+        changes.extend(
+            removed(&new.unknown_tags, &old.unknown_tags)
+                .cloned()
+                .map(Change::UnknownTagAdded),
+        );
+
+        Diff { changes }
+    }
+
+    /// Whether no changes were found.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// The items of `old` missing from `new`.
+fn removed<'a, T: PartialEq>(old: &'a [T], new: &'a [T]) -> impl Iterator<Item = &'a T> {
+    old.iter().filter(move |item| !new.contains(item))
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Change::Protocol { from, to } => write!(f, "protocol changed from {} to {}", Opt(from), Opt(to)),
+            Change::Type { from, to } => write!(f, "type changed from {} to {}", Opt(from), Opt(to)),
+            Change::Model { from, to } => write!(f, "model changed from {} to {}", Name(from), Name(to)),
+            Change::MccsVersion { from, to } => write!(f, "MCCS version changed from {} to {}", Opt(from), Opt(to)),
+            Change::Whql { from, to } => write!(f, "WHQL level changed from {} to {}", Opt(from), Opt(to)),
+            Change::CommandAdded(command) => write!(f, "added command {command} ({:02X})", command.opcode()),
+            Change::CommandRemoved(command) => write!(f, "removed command {command} ({:02X})", command.opcode()),
+            Change::FeatureAdded(feature) => write!(f, "added VCP feature {feature:02X}"),
+            Change::FeatureRemoved(feature) => write!(f, "removed VCP feature {feature:02X}"),
+            Change::FeatureRenamed { feature, from, to } => write!(
+                f,
+                "renamed VCP feature {feature:02X} from {} to {}",
+                Name(from),
+                Name(to)
+            ),
+            Change::ValueAdded { feature, value } => write!(f, "added value {value:02X} to VCP feature {feature:02X}"),
+            Change::ValueRemoved { feature, value } =>
+                write!(f, "removed value {value:02X} from VCP feature {feature:02X}"),
+            Change::ValueRenamed {
+                feature,
+                value,
+                from,
+                to,
+            } => write!(
+                f,
+                "renamed value {value:02X} of VCP feature {feature:02X} from {} to {}",
+                Name(from),
+                Name(to)
+            ),
+            Change::SubValuesChanged {
+                feature,
+                value,
+                from,
+                to,
+            } => write!(
+                f,
+                "changed sub-values of value {value:02X} of VCP feature {feature:02X} from ({}) to ({})",
+                Hex(from),
+                Hex(to)
+            ),
+            Change::Edid { from, to } => write!(f, "EDID changed from {} to {}", Bytes(from), Bytes(to)),
+            Change::VdifAdded(data) => write!(f, "added VDIF block of {} bytes", data.len()),
+            Change::VdifRemoved(data) => write!(f, "removed VDIF block of {} bytes", data.len()),
+            Change::WindowAdded(window) => write!(f, "added window {}", Window(window)),
+            Change::WindowRemoved(window) => write!(f, "removed window {}", Window(window)),
+            Change::AssetEepromSize { from, to } =>
+                write!(f, "asset EEPROM size changed from {} to {}", Opt(from), Opt(to)),
+            Change::FirmwareAdded(version) => write!(f, "added firmware version {}({version})", version.tag),
+            Change::FirmwareRemoved(version) => write!(f, "removed firmware version {}({version})", version.tag),
+            Change::UnknownTagAdded(tag) => write!(f, "added entry {}", Tag(tag)),
+            Change::UnknownTagRemoved(tag) => write!(f, "removed entry {}", Tag(tag)),
+        }
+    }
+}
+
+struct Opt<'a, T>(&'a Option<T>);
+
+impl<T: Display> Display for Opt<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "{value}"),
+            None => f.write_str("none"),
+        }
+    }
+}
+
+struct Name<'a>(&'a Option<String>);
+
+impl Display for Name<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(name) => write!(f, "{name:?}"),
+            None => f.write_str("no name"),
+        }
+    }
+}
+
+struct Hex<'a>(&'a [u8]);
+
+impl Display for Hex<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, value) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{value:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+struct Bytes<'a>(&'a Option<Vec<u8>>);
+
+impl Display for Bytes<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(data) => write!(f, "{} bytes", data.len()),
+            None => f.write_str("none"),
+        }
+    }
+}
+
+struct Window<'a>(&'a WindowCapability);
+
+impl Display for Window<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let window = self.0;
+        write!(f, "{}", window.window)?;
+        if let Some(ty) = &window.ty {
+            write!(f, " ({ty})")?;
+        }
+        Ok(())
+    }
+}
+
+struct Tag<'a>(&'a UnknownTag);
+
+impl Display for Tag<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = &self.0.name;
+        match &self.0.data {
+            UnknownData::String(value) => write!(f, "{name}({value})"),
+            UnknownData::StringBytes(value) => write!(f, "{name}({})", value.escape_ascii()),
+            UnknownData::Binary(data) => write!(f, "{name} bin({} bytes)", data.len()),
+        }
+    }
+}
+
+#[test]
+fn diff_capabilities() {
+    use super::VcpDescriptor;
+    let old = Capabilities {
+        model: Some("P2419H".into()),
+        commands: vec![Command::VcpRequest, Command::SetVcp, Command::SaveSettings],
+        mccs_version: Some(Version::new(2, 1)),
+        vcp_features: [
+            (0x10, VcpDescriptor::default()),
+            (0x14, VcpDescriptor {
+                name: Some("Color Preset".into()),
+                values: [(0x05, None), (0x08, Some("6500K".into()))].into_iter().collect(),
+                ..Default::default()
+            }),
+            (0x60, VcpDescriptor::default()),
+        ]
+        .into_iter()
+        .collect(),
+        unknown_tags: vec![UnknownTag {
+            name: "mpu_ver".into(),
+            data: UnknownData::String("M2T101".into()),
+        }],
+        ..Default::default()
+    };
+    assert!(old.diff(&old).is_empty());
+    assert_eq!(old.diff(&old).to_string(), "");
+
+    let mut new = old.clone();
+    new.model = Some("P2419HC".into());
+    new.commands.retain(|&c| c != Command::SaveSettings);
+    new.commands.push(Command::TableRead);
+    new.mccs_version = Some(Version::new(2, 2));
+    new.vcp_features.remove(&0x60);
+    new.vcp_features.insert(0x62, Default::default());
+    let desc = new.vcp_features.get_mut(&0x14).unwrap();
+    desc.name = Some("Color Temperature".into());
+    desc.values.remove(&0x05);
+    desc.values.insert(0x08, Some("D65".into()));
+    desc.values.insert(0x0b, None);
+    new.unknown_tags[0].data = UnknownData::String("M2T102".into());
+
+    let diff = old.diff(&new);
+    assert_eq!(diff.changes[..3], [
+        Change::Model {
+            from: Some("P2419H".into()),
+            to: Some("P2419HC".into()),
+        },
+        Change::MccsVersion {
+            from: Some(Version::new(2, 1)),
+            to: Some(Version::new(2, 2)),
+        },
+        Change::CommandRemoved(Command::SaveSettings),
+    ]);
+    assert_eq!(
+        diff.to_string(),
+        "model changed from \"P2419H\" to \"P2419HC\"
+MCCS version changed from 2.1 to 2.2
+removed command Save Current Settings (0C)
+added command Table Read (E2)
+renamed VCP feature 14 from \"Color Preset\" to \"Color Temperature\"
+removed value 05 from VCP feature 14
+renamed value 08 of VCP feature 14 from \"6500K\" to \"D65\"
+added value 0B to VCP feature 14
+removed VCP feature 60
+added VCP feature 62
+removed entry mpu_ver(M2T101)
+added entry mpu_ver(M2T102)
+"
+    );
+}
+
+#[test]
+fn diff_every_field() {
+    use super::{FirmwareTag, VcpDescriptor, WindowType};
+
+    let old = Capabilities {
+        vcp_features: [(0x14, VcpDescriptor::default())].into_iter().collect(),
+        ..Default::default()
+    };
+    // Destructuring without `..` stops this from compiling when a field is
+    // added, until the new field is covered here and by `Diff::new`.
+    let Capabilities {
+        protocol,
+        ty,
+        model,
+        commands,
+        ms_whql,
+        mccs_version,
+        vcp_features: _,
+        edid,
+        vdif,
+        windows,
+        asset_eeprom_size,
+        firmware,
+        unknown_tags,
+    } = Capabilities {
+        protocol: Some(Protocol::Monitor),
+        ty: Some(Type::Lcd),
+        model: Some("X".into()),
+        commands: vec![Command::VcpRequest],
+        ms_whql: Some(1),
+        mccs_version: Some(Version::new(2, 2)),
+        vcp_features: Default::default(),
+        edid: Some(vec![0; 128]),
+        vdif: vec![vec![0; 128]],
+        windows: vec![WindowCapability {
+            window: 1,
+            ty: Some(WindowType::Pip),
+            ..Default::default()
+        }],
+        asset_eeprom_size: Some(64),
+        firmware: vec![FirmwareVersion {
+            tag: FirmwareTag::MpuVersion,
+            version: "V2.00".into(),
+            major: 2,
+            minor: Some("00".into()),
+        }],
+        unknown_tags: vec![UnknownTag {
+            name: "foo".into(),
+            data: UnknownData::String("1".into()),
+        }],
+    };
+    let VcpDescriptor {
+        name,
+        values,
+        sub_values,
+    } = VcpDescriptor {
+        name: Some("Color Preset".into()),
+        values: [(0x05, None)].into_iter().collect(),
+        sub_values: [(0x05, vec![0x01])].into_iter().collect(),
+    };
+    let feature = |desc| Capabilities {
+        vcp_features: [(0x14, desc)].into_iter().collect(),
+        ..Default::default()
+    };
+    let changed = [
+        Capabilities {
+            protocol,
+            ..old.clone()
+        },
+        Capabilities { ty, ..old.clone() },
+        Capabilities { model, ..old.clone() },
+        Capabilities {
+            commands,
+            ..old.clone()
+        },
+        Capabilities { ms_whql, ..old.clone() },
+        Capabilities {
+            mccs_version,
+            ..old.clone()
+        },
+        Capabilities { edid, ..old.clone() },
+        Capabilities { vdif, ..old.clone() },
+        Capabilities { windows, ..old.clone() },
+        Capabilities {
+            asset_eeprom_size,
+            ..old.clone()
+        },
+        Capabilities {
+            firmware,
+            ..old.clone()
+        },
+        Capabilities {
+            unknown_tags,
+            ..old.clone()
+        },
+        Capabilities::default(),
+        feature(VcpDescriptor {
+            name,
+            ..Default::default()
+        }),
+        feature(VcpDescriptor {
+            values,
+            ..Default::default()
+        }),
+        feature(VcpDescriptor {
+            sub_values,
+            ..Default::default()
+        }),
+    ];
+    for new in &changed {
+        assert_ne!(&old, new);
+        assert!(!old.diff(new).is_empty(), "{new:?}");
+        assert!(!new.diff(&old).is_empty(), "{new:?}");
+    }
+
+    let mut new = old.clone();
+    new.ms_whql = Some(1);
+    new.edid = Some(vec![0; 128]);
+    new.windows = changed[8].windows.clone();
+    new.asset_eeprom_size = Some(64);
+    new.vcp_features
+        .get_mut(&0x14)
+        .unwrap()
+        .sub_values
+        .insert(0x05, vec![0x01, 0x02]);
+    assert_eq!(
+        old.diff(&new).to_string(),
+        "WHQL level changed from none to 1
+changed sub-values of value 05 of VCP feature 14 from () to (01 02)
+EDID changed from none to 128 bytes
+added window 1 (PIP)
+asset EEPROM size changed from none to 64
+"
+    );
+}
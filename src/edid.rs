@@ -0,0 +1,274 @@
+//! Extended Display Identification Data decoding.
+//!
+//! Only the 128 byte base block is decoded. Extension blocks are counted but
+//! otherwise ignored, so a blob read from the display's EEPROM can be passed in
+//! as-is.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+/// The length of an EDID block.
+pub const BLOCK_LEN: usize = 128;
+
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+/// The contents of an EDID base block.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Edid {
+    /// The three letter PNP ID of the manufacturer, such as `DEL`.
+    pub manufacturer: String,
+    /// The manufacturer's product code.
+    pub product_code: u16,
+    /// The numeric serial number, which is often zero.
+    pub serial_number: u32,
+    /// The week of manufacture, if specified.
+    pub week: Option<u8>,
+    /// The year of manufacture, or the model year if `model_year` is set.
+    pub year: u16,
+    /// Whether `year` is the model year rather than the year of manufacture.
+    pub model_year: bool,
+    /// The EDID structure version and revision.
+    pub version: (u8, u8),
+    /// The display product name descriptor.
+    pub display_name: Option<String>,
+    /// The display serial number descriptor.
+    pub serial: Option<String>,
+    /// The display range limits descriptor.
+    pub range_limits: Option<RangeLimits>,
+    /// The preferred detailed timing, found in the first descriptor.
+    pub preferred_timing: Option<DetailedTiming>,
+    /// The checksum byte of the base block.
+    pub checksum: u8,
+    /// Whether the base block sums to zero as required.
+    pub checksum_valid: bool,
+    /// The number of extension blocks that follow the base block.
+    pub extensions: u8,
+}
+
+/// The display range limits descriptor.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RangeLimits {
+    /// The minimum vertical refresh rate, in Hz.
+    pub min_vertical_hz: u16,
+    /// The maximum vertical refresh rate, in Hz.
+    pub max_vertical_hz: u16,
+    /// The minimum horizontal scan rate, in kHz.
+    pub min_horizontal_khz: u16,
+    /// The maximum horizontal scan rate, in kHz.
+    pub max_horizontal_khz: u16,
+    /// The maximum pixel clock, in MHz, if specified.
+    pub max_pixel_clock_mhz: Option<u16>,
+}
+
+/// A detailed timing descriptor.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DetailedTiming {
+    /// The pixel clock, in kHz.
+    pub pixel_clock_khz: u32,
+    /// The number of visible pixels per line.
+    pub horizontal_active: u16,
+    /// The number of blanking pixels per line.
+    pub horizontal_blanking: u16,
+    /// The number of visible lines.
+    pub vertical_active: u16,
+    /// The number of blanking lines.
+    pub vertical_blanking: u16,
+    /// The number of pixels from the end of the active area to the sync
+    /// pulse.
+    pub horizontal_sync_offset: u16,
+    /// The width of the horizontal sync pulse, in pixels.
+    pub horizontal_sync_width: u16,
+    /// The number of lines from the end of the active area to the sync pulse.
+    pub vertical_sync_offset: u8,
+    /// The width of the vertical sync pulse, in lines.
+    pub vertical_sync_width: u8,
+    /// The width of the image, in millimetres.
+    pub width_mm: u16,
+    /// The height of the image, in millimetres.
+    pub height_mm: u16,
+    /// Whether the timing is interlaced.
+    pub interlaced: bool,
+}
+
+impl DetailedTiming {
+    /// The vertical refresh rate, in mHz.
+    pub fn refresh_rate_mhz(&self) -> u32 {
+        let total = (self.horizontal_active as u64 + self.horizontal_blanking as u64)
+            * (self.vertical_active as u64 + self.vertical_blanking as u64);
+        match total {
+            0 => 0,
+            total => (self.pixel_clock_khz as u64 * 1_000_000 / total) as u32,
+        }
+    }
+}
+
+/// An error encountered while decoding an EDID.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EdidError {
+    /// The data is shorter than the 128 byte base block.
+    Truncated(usize),
+    /// The data does not start with the fixed EDID header.
+    InvalidHeader,
+}
+
+impl Display for EdidError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            EdidError::Truncated(len) => write!(f, "EDID is {len} bytes, expected at least {BLOCK_LEN}"),
+            EdidError::InvalidHeader => f.write_str("invalid EDID header"),
+        }
+    }
+}
+
+impl Error for EdidError {}
+
+impl Edid {
+    /// Decodes the base block at the start of `data`.
+    pub fn parse(data: &[u8]) -> Result<Self, EdidError> {
+        let block = data.get(..BLOCK_LEN).ok_or(EdidError::Truncated(data.len()))?;
+        if block[..8] != HEADER {
+            return Err(EdidError::InvalidHeader)
+        }
+
+        let id = u16::from_be_bytes([block[8], block[9]]);
+        let manufacturer = [10, 5, 0]
+            .iter()
+            .map(|shift| match (id >> shift) & 0x1f {
+                letter @ 1..=26 => (b'A' + letter as u8 - 1) as char,
+                _ => '?',
+            })
+            .collect();
+
+        let mut edid = Edid {
+            manufacturer,
+            product_code: u16::from_le_bytes([block[10], block[11]]),
+            serial_number: u32::from_le_bytes([block[12], block[13], block[14], block[15]]),
+            week: match block[16] {
+                0 | 0xff => None,
+                week => Some(week),
+            },
+            year: 1990 + block[17] as u16,
+            model_year: block[16] == 0xff,
+            version: (block[18], block[19]),
+            display_name: None,
+            serial: None,
+            range_limits: None,
+            preferred_timing: None,
+            checksum: block[127],
+            checksum_valid: block.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0,
+            extensions: block[126],
+        };
+
+        for (i, descriptor) in block[54..126].chunks_exact(18).enumerate() {
+            if descriptor[..2] != [0, 0] {
+                if i == 0 {
+                    edid.preferred_timing = Some(detailed_timing(descriptor));
+                }
+                continue
+            }
+
+            match descriptor[3] {
+                0xfc => edid.display_name = Some(descriptor_text(descriptor)),
+                0xff => edid.serial = Some(descriptor_text(descriptor)),
+                0xfd => edid.range_limits = Some(range_limits(descriptor)),
+                _ => (),
+            }
+        }
+
+        Ok(edid)
+    }
+}
+
+fn detailed_timing(d: &[u8]) -> DetailedTiming {
+    let bits = |byte: u8, shift: u8, mask: u8| ((byte >> shift) & mask) as u16;
+    DetailedTiming {
+        pixel_clock_khz: u16::from_le_bytes([d[0], d[1]]) as u32 * 10,
+        horizontal_active: d[2] as u16 | bits(d[4], 4, 0x0f) << 8,
+        horizontal_blanking: d[3] as u16 | bits(d[4], 0, 0x0f) << 8,
+        vertical_active: d[5] as u16 | bits(d[7], 4, 0x0f) << 8,
+        vertical_blanking: d[6] as u16 | bits(d[7], 0, 0x0f) << 8,
+        horizontal_sync_offset: d[8] as u16 | bits(d[11], 6, 0x03) << 8,
+        horizontal_sync_width: d[9] as u16 | bits(d[11], 4, 0x03) << 8,
+        vertical_sync_offset: (d[10] >> 4) | ((d[11] >> 2) & 0x03) << 4,
+        vertical_sync_width: (d[10] & 0x0f) | (d[11] & 0x03) << 4,
+        width_mm: d[12] as u16 | bits(d[14], 4, 0x0f) << 8,
+        height_mm: d[13] as u16 | bits(d[14], 0, 0x0f) << 8,
+        interlaced: d[17] & 0x80 != 0,
+    }
+}
+
+fn descriptor_text(d: &[u8]) -> String {
+    let text = &d[5..];
+    let text = text.split(|&c| c == b'\n').next().unwrap_or(text);
+    String::from_utf8_lossy(text).trim_end().into()
+}
+
+fn range_limits(d: &[u8]) -> RangeLimits {
+    // EDID 1.4 flags extending each rate by 255
+    let offset = |bit: u8| match d[4] & (1 << bit) {
+        0 => 0,
+        _ => 255,
+    };
+    RangeLimits {
+        min_vertical_hz: d[5] as u16 + offset(0),
+        max_vertical_hz: d[6] as u16 + offset(1),
+        min_horizontal_khz: d[7] as u16 + offset(2),
+        max_horizontal_khz: d[8] as u16 + offset(3),
+        max_pixel_clock_mhz: match d[9] {
+            0 => None,
+            clock => Some(clock as u16 * 10),
+        },
+    }
+}
+
+#[test]
+fn decode_edid() {
+    let mut block = [0u8; 256];
+    block[..8].copy_from_slice(&HEADER);
+    // "DEL", 0x4083, serial 0x12345678, week 12 of 2019, EDID 1.4
+    block[8..20].copy_from_slice(&[0x10, 0xac, 0x83, 0x40, 0x78, 0x56, 0x34, 0x12, 12, 29, 1, 4]);
+    // 1920x1080@60, 148.5MHz, 527x296mm
+    block[54..72].copy_from_slice(&[
+        0x02, 0x3a, 0x80, 0x18, 0x71, 0x38, 0x2d, 0x40, 0x58, 0x2c, 0x45, 0x00, 0x0f, 0x28, 0x21, 0x00, 0x00, 0x1e,
+    ]);
+    block[72..90].copy_from_slice(b"\0\0\0\xff\0ABC123\n      ");
+    block[90..108].copy_from_slice(b"\0\0\0\xfc\0DELL P2419H\n ");
+    block[108..126].copy_from_slice(b"\0\0\0\xfd\x00\x32\x4c\x1e\x53\x11\x00\x0a\x20\x20\x20\x20\x20\x20");
+    block[126] = 1;
+    block[127] = block[..127].iter().fold(0u8, |sum, &b| sum.wrapping_sub(b));
+
+    let edid = Edid::parse(&block).unwrap();
+    assert_eq!(edid.manufacturer, "DEL");
+    assert_eq!(edid.product_code, 0x4083);
+    assert_eq!(edid.serial_number, 0x12345678);
+    assert_eq!((edid.week, edid.year, edid.model_year), (Some(12), 2019, false));
+    assert_eq!(edid.version, (1, 4));
+    assert_eq!(edid.display_name.as_deref(), Some("DELL P2419H"));
+    assert_eq!(edid.serial.as_deref(), Some("ABC123"));
+    assert_eq!(
+        edid.range_limits,
+        Some(RangeLimits {
+            min_vertical_hz: 50,
+            max_vertical_hz: 76,
+            min_horizontal_khz: 30,
+            max_horizontal_khz: 83,
+            max_pixel_clock_mhz: Some(170),
+        })
+    );
+    let timing = edid.preferred_timing.unwrap();
+    assert_eq!((timing.horizontal_active, timing.vertical_active), (1920, 1080));
+    assert_eq!((timing.horizontal_blanking, timing.vertical_blanking), (280, 45));
+    assert_eq!((timing.width_mm, timing.height_mm), (527, 296));
+    assert_eq!(timing.pixel_clock_khz, 148_500);
+    assert_eq!(timing.refresh_rate_mhz(), 60_000);
+    assert!(edid.checksum_valid);
+    assert_eq!(edid.extensions, 1);
+
+    block[127] ^= 1;
+    assert!(!Edid::parse(&block[..128]).unwrap().checksum_valid);
+    assert_eq!(Edid::parse(&block[..100]), Err(EdidError::Truncated(100)));
+    block[0] = 1;
+    assert_eq!(Edid::parse(&block), Err(EdidError::InvalidHeader));
+}
@@ -1,5 +1,5 @@
 #![deny(missing_docs)]
-#![doc(html_root_url = "https://docs.rs/mccs/0.2.0")]
+#![doc(html_root_url = "https://docs.rs/mccs/0.3.0")]
 
 //! VESA Monitor Command Control Set standardizes the meaning of DDC/CI VCP
 //! feature codes, and allows a display to broadcast its capabilities to the
@@ -12,6 +12,13 @@ use std::{
     str::FromStr,
 };
 
+pub mod builder;
+pub mod diff;
+pub mod edid;
+pub mod packet;
+pub mod transfer;
+pub mod vdif;
+
 /// VCP feature code
 pub type FeatureCode = u8;
 
@@ -108,7 +115,7 @@ pub struct Capabilities {
     /// The model name/number of the display.
     pub model: Option<String>,
     /// A list of the supported VCP commands.
-    pub commands: Vec<u8>,
+    pub commands: Vec<Command>,
     /// A value of `1` seems to indicate that the monitor has passed Microsoft's
     /// Windows Hardware Quality Labs testing.
     pub ms_whql: Option<u8>,
@@ -125,10 +132,215 @@ pub struct Capabilities {
     /// Video Display Information Format are optional extension blocks for the
     /// EDID. Like the EDID field, this is probably not in use.
     pub vdif: Vec<VdifData>,
+    /// Picture-in-picture and picture-by-picture windows, as described in
+    /// MCCS 2.2.
+    pub windows: Vec<WindowCapability>,
+    /// The size of the asset tag EEPROM in bytes, reported by vendors as
+    /// `asset_eep()`.
+    pub asset_eeprom_size: Option<u16>,
+    /// Firmware versions reported by vendor specific entries such as
+    /// `mpu_ver()`.
+    pub firmware: Vec<FirmwareVersion>,
     /// Additional unrecognized data from the capability string.
     pub unknown_tags: Vec<UnknownTag>,
 }
 
+impl Capabilities {
+    /// Starts building capabilities one entry at a time.
+    pub fn builder() -> builder::CapabilitiesBuilder {
+        builder::CapabilitiesBuilder::new()
+    }
+
+    /// Decodes the EDID reported in the capability string, if any.
+    pub fn decode_edid(&self) -> Option<Result<edid::Edid, edid::EdidError>> {
+        self.edid.as_deref().map(edid::Edid::parse)
+    }
+
+    /// Decodes each VDIF block reported in the capability string.
+    pub fn decode_vdif(&self) -> impl Iterator<Item = Result<vdif::Vdif, vdif::VdifError>> + '_ {
+        self.vdif.iter().map(|data| vdif::Vdif::parse(data))
+    }
+
+    /// Lists what changed between these capabilities and `new`.
+    pub fn diff(&self, new: &Capabilities) -> diff::Diff {
+        diff::Diff::new(self, new)
+    }
+
+    /// Converts the capabilities into a canonical form, so that capabilities
+    /// differing only in case, order or repeated entries compare equal.
+    pub fn normalize(&mut self) {
+        if let Some(Protocol::Unknown(protocol)) = &self.protocol {
+            self.protocol = Some(protocol.to_ascii_lowercase().as_str().into());
+        }
+        if let Some(Type::Unknown(ty)) = &self.ty {
+            self.ty = Some(ty.to_ascii_lowercase().as_str().into());
+        }
+        self.commands.sort_by_key(Command::opcode);
+        self.commands.dedup();
+        for window in &mut self.windows {
+            if let Some(WindowType::Unknown(ty)) = &window.ty {
+                window.ty = Some(ty.to_ascii_lowercase().as_str().into());
+            }
+            window.features.sort();
+            window.features.dedup();
+            window.unknown.sort();
+            window.unknown.dedup();
+        }
+        self.windows.sort();
+        self.windows.dedup();
+        self.firmware.sort();
+        self.firmware.dedup();
+        self.unknown_tags.sort();
+        self.unknown_tags.dedup();
+    }
+
+    /// Whether the display lists `command` as supported.
+    pub fn supports(&self, command: Command) -> bool {
+        self.commands.contains(&command)
+    }
+
+    /// Whether the display supports reading VCP table features.
+    pub fn supports_table_read(&self) -> bool {
+        self.supports(Command::TableRead)
+    }
+
+    /// Whether the display supports writing VCP table features.
+    pub fn supports_table_write(&self) -> bool {
+        self.supports(Command::TableWrite)
+    }
+
+    /// Whether the display supports saving its current settings.
+    pub fn supports_save_settings(&self) -> bool {
+        self.supports(Command::SaveSettings)
+    }
+
+    /// Whether the display supports reporting its current timing.
+    pub fn supports_timing_report(&self) -> bool {
+        self.supports(Command::TimingRequest)
+    }
+}
+
+/// DDC/CI command opcode
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Command {
+    /// Get VCP Feature, `0x01`.
+    VcpRequest,
+    /// Get VCP Feature Reply, `0x02`.
+    VcpReply,
+    /// Set VCP Feature, `0x03`.
+    SetVcp,
+    /// Get Timing Report, `0x07`.
+    TimingRequest,
+    /// Save Current Settings, `0x0C`.
+    SaveSettings,
+    /// Timing Reply, `0x4E`.
+    TimingReply,
+    /// Application Test, `0xB1`.
+    ApplicationTest,
+    /// Identification Reply, `0xE1`.
+    IdentificationReply,
+    /// Table Read, `0xE2`.
+    TableRead,
+    /// Capabilities Reply, `0xE3`.
+    CapabilitiesReply,
+    /// Table Read Reply, `0xE4`.
+    TableReadReply,
+    /// Table Write, `0xE7`.
+    TableWrite,
+    /// Identification Request, `0xF1`.
+    IdentificationRequest,
+    /// Capabilities Request, `0xF3`.
+    CapabilitiesRequest,
+    /// Enable Application Report, `0xF5`.
+    EnableApplicationReport,
+    /// Unrecognized command opcode
+    Unknown(u8),
+}
+
+impl Command {
+    /// The opcode of this command.
+    pub fn opcode(&self) -> u8 {
+        match *self {
+            Command::VcpRequest => 0x01,
+            Command::VcpReply => 0x02,
+            Command::SetVcp => 0x03,
+            Command::TimingRequest => 0x07,
+            Command::SaveSettings => 0x0c,
+            Command::TimingReply => 0x4e,
+            Command::ApplicationTest => 0xb1,
+            Command::IdentificationReply => 0xe1,
+            Command::TableRead => 0xe2,
+            Command::CapabilitiesReply => 0xe3,
+            Command::TableReadReply => 0xe4,
+            Command::TableWrite => 0xe7,
+            Command::IdentificationRequest => 0xf1,
+            Command::CapabilitiesRequest => 0xf3,
+            Command::EnableApplicationReport => 0xf5,
+            Command::Unknown(opcode) => opcode,
+        }
+    }
+
+    /// A descriptive name for the command, if it is recognized.
+    pub fn name(&self) -> Option<&'static str> {
+        Some(match *self {
+            Command::VcpRequest => "Get VCP Feature",
+            Command::VcpReply => "Get VCP Feature Reply",
+            Command::SetVcp => "Set VCP Feature",
+            Command::TimingRequest => "Get Timing Report",
+            Command::SaveSettings => "Save Current Settings",
+            Command::TimingReply => "Timing Reply",
+            Command::ApplicationTest => "Application Test",
+            Command::IdentificationReply => "Identification Reply",
+            Command::TableRead => "Table Read",
+            Command::CapabilitiesReply => "Capabilities Reply",
+            Command::TableReadReply => "Table Read Reply",
+            Command::TableWrite => "Table Write",
+            Command::IdentificationRequest => "Identification Request",
+            Command::CapabilitiesRequest => "Capabilities Request",
+            Command::EnableApplicationReport => "Enable Application Report",
+            Command::Unknown(..) => return None,
+        })
+    }
+}
+
+impl From<u8> for Command {
+    fn from(opcode: u8) -> Self {
+        match opcode {
+            0x01 => Command::VcpRequest,
+            0x02 => Command::VcpReply,
+            0x03 => Command::SetVcp,
+            0x07 => Command::TimingRequest,
+            0x0c => Command::SaveSettings,
+            0x4e => Command::TimingReply,
+            0xb1 => Command::ApplicationTest,
+            0xe1 => Command::IdentificationReply,
+            0xe2 => Command::TableRead,
+            0xe3 => Command::CapabilitiesReply,
+            0xe4 => Command::TableReadReply,
+            0xe7 => Command::TableWrite,
+            0xf1 => Command::IdentificationRequest,
+            0xf3 => Command::CapabilitiesRequest,
+            0xf5 => Command::EnableApplicationReport,
+            opcode => Command::Unknown(opcode),
+        }
+    }
+}
+
+impl From<Command> for u8 {
+    fn from(command: Command) -> Self {
+        command.opcode()
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "Unknown command {:02X}", self.opcode()),
+        }
+    }
+}
+
 /// Display protocol class
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Protocol {
@@ -150,16 +362,20 @@ impl<'a> From<&'a str> for Protocol {
     }
 }
 
+impl Protocol {
+    /// The protocol class as it appears in a capability string.
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Protocol::Monitor => "monitor",
+            Protocol::Display => "display",
+            Protocol::Unknown(ref s) => s,
+        }
+    }
+}
+
 impl Display for Protocol {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        Display::fmt(
-            match *self {
-                Protocol::Monitor => "monitor",
-                Protocol::Display => "display",
-                Protocol::Unknown(ref s) => s,
-            },
-            f,
-        )
+        Display::fmt(self.as_str(), f)
     }
 }
 
@@ -195,17 +411,21 @@ impl<'a> From<&'a str> for Type {
     }
 }
 
+impl Type {
+    /// The display type as it appears in a capability string.
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Type::Crt => "crt",
+            Type::Lcd => "lcd",
+            Type::Led => "led",
+            Type::Unknown(ref s) => s,
+        }
+    }
+}
+
 impl Display for Type {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        Display::fmt(
-            match *self {
-                Type::Crt => "crt",
-                Type::Lcd => "lcd",
-                Type::Led => "led",
-                Type::Unknown(ref s) => s,
-            },
-            f,
-        )
+        Display::fmt(self.as_str(), f)
     }
 }
 
@@ -248,13 +468,21 @@ pub struct VcpDescriptor {
     ///
     /// This is used for non-continuous VCP types.
     pub values: ValueNames,
+    /// Nested sub-values declared for some of the allowed values, such as the
+    /// `01 02` in `vcp(14(05(01 02)))`.
+    pub sub_values: BTreeMap<u8, Vec<u8>>,
 }
 
 impl VcpDescriptor {
     /// The allowed values for this feature code.
-    pub fn values(&self) -> btree_map::Keys<u8, Option<String>> {
+    pub fn values(&self) -> btree_map::Keys<'_, u8, Option<String>> {
         self.values.keys()
     }
+
+    /// The sub-values declared for an allowed value, if any.
+    pub fn sub_values(&self, value: u8) -> &[u8] {
+        self.sub_values.get(&value).map(|v| &v[..]).unwrap_or_default()
+    }
 }
 
 /// An unrecognized entry in the capability string
@@ -266,6 +494,147 @@ pub struct UnknownTag {
     pub data: UnknownData,
 }
 
+/// A firmware version reported by a vendor specific capability entry.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    /// The entry the version was reported by.
+    pub tag: FirmwareTag,
+    /// The version as written in the capability string, such as `V2.00`.
+    pub version: String,
+    /// The major version number.
+    pub major: u32,
+    /// The digits of the minor version as written, such as `00` in `V2.00`.
+    ///
+    /// These are kept verbatim because vendors disagree on whether `2.05`
+    /// and `2.5` are the same version.
+    pub minor: Option<String>,
+}
+
+impl Display for FirmwareVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.version, f)
+    }
+}
+
+/// Vendor specific capability entries that report a firmware version.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FirmwareTag {
+    /// `mpu_ver()`, the version of the display's microcontroller firmware.
+    MpuVersion,
+    /// `mpu()`, also the microcontroller firmware version.
+    Mpu,
+    /// `fw()`
+    Firmware,
+    /// `version()`
+    Version,
+}
+
+impl FirmwareTag {
+    /// All of the recognized entries.
+    pub const ALL: [FirmwareTag; 4] = [
+        FirmwareTag::MpuVersion,
+        FirmwareTag::Mpu,
+        FirmwareTag::Firmware,
+        FirmwareTag::Version,
+    ];
+
+    /// The name of the entry as it appears in a capability string.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            FirmwareTag::MpuVersion => "mpu_ver",
+            FirmwareTag::Mpu => "mpu",
+            FirmwareTag::Firmware => "fw",
+            FirmwareTag::Version => "version",
+        }
+    }
+}
+
+impl Display for FirmwareTag {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+/// A picture-in-picture or picture-by-picture window described by a
+/// `windowN()` capability entry.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WindowCapability {
+    /// The window number, as in `window1`.
+    pub window: u8,
+    /// The kind of window.
+    pub ty: Option<WindowType>,
+    /// The region of the screen the window may occupy.
+    pub area: Option<WindowArea>,
+    /// The maximum width and height of the window.
+    pub max_size: Option<(u16, u16)>,
+    /// The minimum width and height of the window.
+    pub min_size: Option<(u16, u16)>,
+    /// The VCP feature codes that apply to the window.
+    pub features: Vec<FeatureCode>,
+    /// Fields that were not recognized, such as those from newer revisions
+    /// of the specification.
+    pub unknown: Vec<UnknownTag>,
+}
+
+/// Window type
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WindowType {
+    /// Picture-in-picture, overlapping the main image.
+    Pip,
+    /// Picture-by-picture, placed beside the main image.
+    Pbp,
+    /// Unrecognized window type
+    Unknown(String),
+}
+
+impl<'a> From<&'a str> for WindowType {
+    fn from(s: &'a str) -> Self {
+        match s {
+            s if s.eq_ignore_ascii_case("pip") => WindowType::Pip,
+            s if s.eq_ignore_ascii_case("pbp") => WindowType::Pbp,
+            s => WindowType::Unknown(s.into()),
+        }
+    }
+}
+
+impl WindowType {
+    /// The window type as it appears in a capability string.
+    pub fn as_str(&self) -> &str {
+        match *self {
+            WindowType::Pip => "PIP",
+            WindowType::Pbp => "PBP",
+            WindowType::Unknown(ref s) => s,
+        }
+    }
+}
+
+impl Display for WindowType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+impl FromStr for WindowType {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+/// A rectangular region of the screen, in pixels.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WindowArea {
+    /// The left edge.
+    pub left: u16,
+    /// The top edge.
+    pub top: u16,
+    /// The right edge.
+    pub right: u16,
+    /// The bottom edge.
+    pub bottom: u16,
+}
+
 /// Data that can be contained in a capability entry.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum UnknownData {
@@ -0,0 +1,509 @@
+//! DDC/CI message framing.
+//!
+//! Messages are exchanged over I2C with the display at address `0x37`. Each
+//! frame starts with the address of its sender, followed by a length byte
+//! with bit 7 set, the payload, and an XOR checksum. Frames written by the
+//! host are checksummed along with the display's write address `0x6E`, while
+//! replies from the display are checksummed with a virtual host address of
+//! `0x50`.
+
+use {
+    super::{Command, FeatureCode, Value},
+    std::{
+        error::Error,
+        fmt::{self, Display, Formatter},
+    },
+};
+
+/// The 7-bit I2C address of a display's DDC/CI interface.
+pub const I2C_ADDRESS: u16 = 0x37;
+
+/// The address of the display, which starts each of its replies.
+pub const DISPLAY_ADDRESS: u8 = 0x6e;
+
+/// The address of the host, which starts each frame it sends.
+pub const HOST_ADDRESS: u8 = 0x51;
+
+/// The address of the host as included in the checksum of display replies.
+pub const HOST_VIRTUAL_ADDRESS: u8 = 0x50;
+
+/// The largest fragment of a capability string or table carried by a single
+/// message.
+pub const MAX_DATA_LEN: usize = 32;
+
+/// A message sent from the host to the display.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HostMessage<'a> {
+    /// An empty message.
+    Null,
+    /// Requests the current value of a VCP feature.
+    VcpRequest {
+        /// The feature code.
+        feature: FeatureCode,
+    },
+    /// Sets the value of a VCP feature.
+    SetVcp {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The new value.
+        value: u16,
+    },
+    /// Requests the current display timing.
+    TimingRequest,
+    /// Asks the display to save its current settings.
+    SaveSettings,
+    /// Requests a fragment of the capability string.
+    CapabilitiesRequest {
+        /// The offset of the fragment.
+        offset: u16,
+    },
+    /// Requests a fragment of a table VCP feature.
+    TableRead {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The offset of the fragment.
+        offset: u16,
+    },
+    /// Writes a fragment of a table VCP feature.
+    TableWrite {
+        /// The feature code.
+        feature: FeatureCode,
+        /// The offset of the fragment.
+        offset: u16,
+        /// The data to write.
+        data: &'a [u8],
+    },
+}
+
+/// A message sent from the display to the host.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DisplayMessage<'a> {
+    /// An empty message, sent when the display has nothing to report or is
+    /// not ready to reply.
+    Null,
+    /// The current value of a VCP feature.
+    VcpReply {
+        /// The feature code.
+        feature: FeatureCode,
+        /// Whether the display supports the feature.
+        supported: bool,
+        /// The current and maximum values.
+        value: Value,
+    },
+    /// The current display timing.
+    TimingReply {
+        /// Timing status flags.
+        status: u8,
+        /// The horizontal frequency, in units of 10 Hz.
+        horizontal_frequency: u16,
+        /// The vertical frequency, in units of 0.01 Hz.
+        vertical_frequency: u16,
+    },
+    /// A fragment of the capability string.
+    CapabilitiesReply {
+        /// The offset of the fragment.
+        offset: u16,
+        /// The fragment, empty once the end of the string is reached.
+        data: &'a [u8],
+    },
+    /// A fragment of a table VCP feature.
+    TableReadReply {
+        /// The offset of the fragment.
+        offset: u16,
+        /// The fragment, empty once the end of the table is reached.
+        data: &'a [u8],
+    },
+}
+
+/// An error encountered while encoding or decoding a DDC/CI frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PacketError {
+    /// The frame was not sent from the expected address.
+    InvalidSource(u8),
+    /// The length byte does not match the frame or message.
+    InvalidLength(u8),
+    /// The checksum does not match the contents of the frame.
+    InvalidChecksum {
+        /// The checksum of the frame contents.
+        expected: u8,
+        /// The checksum included in the frame.
+        found: u8,
+    },
+    /// The message type is not valid in this direction.
+    UnexpectedOpcode(u8),
+}
+
+impl Display for PacketError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            PacketError::InvalidSource(source) => write!(f, "unexpected DDC/CI source address {source:02X}"),
+            PacketError::InvalidLength(len) => write!(f, "invalid DDC/CI length {len:02X}"),
+            PacketError::InvalidChecksum { expected, found } =>
+                write!(f, "DDC/CI checksum {found:02X} does not match {expected:02X}"),
+            PacketError::UnexpectedOpcode(opcode) => write!(f, "unexpected DDC/CI opcode {opcode:02X}"),
+        }
+    }
+}
+
+impl Error for PacketError {}
+
+impl<'a> HostMessage<'a> {
+    /// The command this message carries, or `None` for a null message.
+    pub fn command(&self) -> Option<Command> {
+        Some(match self {
+            HostMessage::Null => return None,
+            HostMessage::VcpRequest { .. } => Command::VcpRequest,
+            HostMessage::SetVcp { .. } => Command::SetVcp,
+            HostMessage::TimingRequest => Command::TimingRequest,
+            HostMessage::SaveSettings => Command::SaveSettings,
+            HostMessage::CapabilitiesRequest { .. } => Command::CapabilitiesRequest,
+            HostMessage::TableRead { .. } => Command::TableRead,
+            HostMessage::TableWrite { .. } => Command::TableWrite,
+        })
+    }
+
+    /// Encodes the message into a frame, starting with the host address.
+    pub fn encode(&self) -> Result<Vec<u8>, PacketError> {
+        let mut payload = Vec::new();
+        payload.extend(self.command().map(|command| command.opcode()));
+        match *self {
+            HostMessage::Null | HostMessage::TimingRequest | HostMessage::SaveSettings => (),
+            HostMessage::VcpRequest { feature } => payload.push(feature),
+            HostMessage::SetVcp { feature, value } => {
+                payload.push(feature);
+                payload.extend(value.to_be_bytes());
+            },
+            HostMessage::CapabilitiesRequest { offset } => payload.extend(offset.to_be_bytes()),
+            HostMessage::TableRead { feature, offset } => {
+                payload.push(feature);
+                payload.extend(offset.to_be_bytes());
+            },
+            HostMessage::TableWrite { feature, offset, data } => {
+                payload.push(feature);
+                payload.extend(offset.to_be_bytes());
+                payload.extend(data);
+                fragment(data, length_byte(&payload))?;
+            },
+        }
+        Ok(encode_frame(HOST_ADDRESS, DISPLAY_ADDRESS, &payload))
+    }
+
+    /// Decodes a frame sent by the host, starting with the host address.
+    ///
+    /// Any bytes following the checksum are ignored.
+    pub fn decode(frame: &'a [u8]) -> Result<Self, PacketError> {
+        let payload = decode_frame(frame, HOST_ADDRESS, DISPLAY_ADDRESS)?;
+        let (&opcode, args) = match payload.split_first() {
+            Some(split) => split,
+            None => return Ok(HostMessage::Null),
+        };
+        let len = frame[1];
+        Ok(match Command::from(opcode) {
+            Command::VcpRequest => match *args {
+                [feature] => HostMessage::VcpRequest { feature },
+                _ => return Err(PacketError::InvalidLength(len)),
+            },
+            Command::SetVcp => match *args {
+                [feature, vh, vl] => HostMessage::SetVcp {
+                    feature,
+                    value: u16::from_be_bytes([vh, vl]),
+                },
+                _ => return Err(PacketError::InvalidLength(len)),
+            },
+            Command::TimingRequest if args.is_empty() => HostMessage::TimingRequest,
+            Command::SaveSettings if args.is_empty() => HostMessage::SaveSettings,
+            Command::CapabilitiesRequest => match *args {
+                [oh, ol] => HostMessage::CapabilitiesRequest {
+                    offset: u16::from_be_bytes([oh, ol]),
+                },
+                _ => return Err(PacketError::InvalidLength(len)),
+            },
+            Command::TableRead => match *args {
+                [feature, oh, ol] => HostMessage::TableRead {
+                    feature,
+                    offset: u16::from_be_bytes([oh, ol]),
+                },
+                _ => return Err(PacketError::InvalidLength(len)),
+            },
+            Command::TableWrite => match *args {
+                [feature, oh, ol, ref data @ ..] => HostMessage::TableWrite {
+                    feature,
+                    offset: u16::from_be_bytes([oh, ol]),
+                    data: fragment(data, len)?,
+                },
+                _ => return Err(PacketError::InvalidLength(len)),
+            },
+            Command::TimingRequest | Command::SaveSettings => return Err(PacketError::InvalidLength(len)),
+            _ => return Err(PacketError::UnexpectedOpcode(opcode)),
+        })
+    }
+}
+
+impl<'a> DisplayMessage<'a> {
+    /// The command this message carries, or `None` for a null message.
+    pub fn command(&self) -> Option<Command> {
+        Some(match self {
+            DisplayMessage::Null => return None,
+            DisplayMessage::VcpReply { .. } => Command::VcpReply,
+            DisplayMessage::TimingReply { .. } => Command::TimingReply,
+            DisplayMessage::CapabilitiesReply { .. } => Command::CapabilitiesReply,
+            DisplayMessage::TableReadReply { .. } => Command::TableReadReply,
+        })
+    }
+
+    /// Encodes the message into a frame, starting with the display address.
+    pub fn encode(&self) -> Result<Vec<u8>, PacketError> {
+        let mut payload = Vec::new();
+        payload.extend(self.command().map(|command| command.opcode()));
+        match *self {
+            DisplayMessage::Null => (),
+            DisplayMessage::VcpReply {
+                feature,
+                supported,
+                value,
+            } => payload.extend([
+                !supported as u8,
+                feature,
+                value.ty,
+                value.mh,
+                value.ml,
+                value.sh,
+                value.sl,
+            ]),
+            DisplayMessage::TimingReply {
+                status,
+                horizontal_frequency,
+                vertical_frequency,
+            } => {
+                payload.push(status);
+                payload.extend(horizontal_frequency.to_be_bytes());
+                payload.extend(vertical_frequency.to_be_bytes());
+            },
+            DisplayMessage::CapabilitiesReply { offset, data } | DisplayMessage::TableReadReply { offset, data } => {
+                payload.extend(offset.to_be_bytes());
+                payload.extend(data);
+                fragment(data, length_byte(&payload))?;
+            },
+        }
+        Ok(encode_frame(DISPLAY_ADDRESS, HOST_VIRTUAL_ADDRESS, &payload))
+    }
+
+    /// Decodes a frame sent by the display, starting with the display
+    /// address.
+    ///
+    /// Any bytes following the checksum are ignored, since replies are
+    /// usually read into a buffer of a fixed size.
+    pub fn decode(frame: &'a [u8]) -> Result<Self, PacketError> {
+        let payload = decode_frame(frame, DISPLAY_ADDRESS, HOST_VIRTUAL_ADDRESS)?;
+        let (&opcode, args) = match payload.split_first() {
+            Some(split) => split,
+            None => return Ok(DisplayMessage::Null),
+        };
+        let len = frame[1];
+        Ok(match Command::from(opcode) {
+            Command::VcpReply => match *args {
+                [result, feature, ty, mh, ml, sh, sl] => DisplayMessage::VcpReply {
+                    feature,
+                    supported: result == 0,
+                    value: Value { ty, mh, ml, sh, sl },
+                },
+                _ => return Err(PacketError::InvalidLength(len)),
+            },
+            Command::TimingReply => match *args {
+                [status, hh, hl, vh, vl] => DisplayMessage::TimingReply {
+                    status,
+                    horizontal_frequency: u16::from_be_bytes([hh, hl]),
+                    vertical_frequency: u16::from_be_bytes([vh, vl]),
+                },
+                _ => return Err(PacketError::InvalidLength(len)),
+            },
+            Command::CapabilitiesReply => match *args {
+                [oh, ol, ref data @ ..] => DisplayMessage::CapabilitiesReply {
+                    offset: u16::from_be_bytes([oh, ol]),
+                    data: fragment(data, len)?,
+                },
+                _ => return Err(PacketError::InvalidLength(len)),
+            },
+            Command::TableReadReply => match *args {
+                [oh, ol, ref data @ ..] => DisplayMessage::TableReadReply {
+                    offset: u16::from_be_bytes([oh, ol]),
+                    data: fragment(data, len)?,
+                },
+                _ => return Err(PacketError::InvalidLength(len)),
+            },
+            _ => return Err(PacketError::UnexpectedOpcode(opcode)),
+        })
+    }
+}
+
+/// Checks that a capability string or table fragment fits in one message.
+fn fragment(data: &[u8], len: u8) -> Result<&[u8], PacketError> {
+    match data.len() {
+        0..=MAX_DATA_LEN => Ok(data),
+        _ => Err(PacketError::InvalidLength(len)),
+    }
+}
+
+/// The length byte of a frame carrying `payload`.
+fn length_byte(payload: &[u8]) -> u8 {
+    0x80 | payload.len().min(0x7f) as u8
+}
+
+fn checksum(init: u8, data: &[u8]) -> u8 {
+    data.iter().fold(init, |checksum, &b| checksum ^ b)
+}
+
+fn encode_frame(source: u8, checksum_address: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 3);
+    frame.push(source);
+    frame.push(length_byte(payload));
+    frame.extend_from_slice(payload);
+    frame.push(checksum(checksum_address, &frame));
+    frame
+}
+
+fn decode_frame(frame: &[u8], source: u8, checksum_address: u8) -> Result<&[u8], PacketError> {
+    let (&found_source, &len) = match frame {
+        [source, len, ..] => (source, len),
+        _ => return Err(PacketError::InvalidLength(0)),
+    };
+    if found_source != source {
+        return Err(PacketError::InvalidSource(found_source))
+    }
+    // the timing reply is documented without the length flag
+    let payload_len = match len {
+        0x06 if frame.get(2) == Some(&Command::TimingReply.opcode()) => 6,
+        len if len & 0x80 != 0 => (len & 0x7f) as usize,
+        len => return Err(PacketError::InvalidLength(len)),
+    };
+    let (contents, found) = match frame.get(..payload_len + 3) {
+        Some([contents @ .., found]) => (contents, *found),
+        _ => return Err(PacketError::InvalidLength(len)),
+    };
+    let expected = checksum(checksum_address, contents);
+    if found != expected {
+        return Err(PacketError::InvalidChecksum { expected, found })
+    }
+    Ok(&contents[2..])
+}
+
+#[test]
+fn encode_messages() {
+    assert_eq!(HostMessage::Null.encode().unwrap(), [0x51, 0x80, 0xbf]);
+    assert_eq!(HostMessage::VcpRequest { feature: 0x10 }.encode().unwrap(), [
+        0x51, 0x82, 0x01, 0x10, 0xac
+    ]);
+    assert_eq!(
+        HostMessage::SetVcp {
+            feature: 0x10,
+            value: 0x0032
+        }
+        .encode()
+        .unwrap(),
+        [0x51, 0x84, 0x03, 0x10, 0x00, 0x32, 0x9a]
+    );
+    assert_eq!(HostMessage::CapabilitiesRequest { offset: 0 }.encode().unwrap(), [
+        0x51, 0x83, 0xf3, 0x00, 0x00, 0x4f
+    ]);
+    assert_eq!(HostMessage::SaveSettings.encode().unwrap(), [0x51, 0x81, 0x0c, 0xb2]);
+    assert_eq!(DisplayMessage::Null.encode().unwrap(), [0x6e, 0x80, 0xbe]);
+    assert_eq!(
+        HostMessage::TableWrite {
+            feature: 0x73,
+            offset: 0,
+            data: &[0; 33]
+        }
+        .encode(),
+        Err(PacketError::InvalidLength(0xa5))
+    );
+}
+
+#[test]
+fn decode_messages() {
+    let reply = [0x6e, 0x88, 0x02, 0x00, 0x10, 0x00, 0x00, 0x64, 0x00, 0x32, 0xf2];
+    assert_eq!(
+        DisplayMessage::decode(&reply),
+        Ok(DisplayMessage::VcpReply {
+            feature: 0x10,
+            supported: true,
+            value: Value {
+                ty: 0,
+                mh: 0,
+                ml: 0x64,
+                sh: 0,
+                sl: 0x32
+            },
+        })
+    );
+    assert_eq!(DisplayMessage::decode(&reply).unwrap().encode().unwrap(), reply);
+
+    let mut padded = reply.to_vec();
+    padded.resize(38, 0xff);
+    assert!(DisplayMessage::decode(&padded).is_ok());
+    assert_eq!(
+        DisplayMessage::decode(&reply[..10]),
+        Err(PacketError::InvalidLength(0x88))
+    );
+    let mut corrupted = reply;
+    corrupted[9] = 0x33;
+    assert_eq!(
+        DisplayMessage::decode(&corrupted),
+        Err(PacketError::InvalidChecksum {
+            expected: 0xf3,
+            found: 0xf2
+        })
+    );
+    assert_eq!(
+        DisplayMessage::decode(&[0x51, 0x80, 0xbf]),
+        Err(PacketError::InvalidSource(0x51))
+    );
+    assert_eq!(
+        DisplayMessage::decode(&HostMessage::SaveSettings.encode().unwrap()[..]),
+        Err(PacketError::InvalidSource(0x51))
+    );
+
+    let caps = DisplayMessage::CapabilitiesReply {
+        offset: 0x20,
+        data: b"(prot(monitor)",
+    };
+    assert_eq!(DisplayMessage::decode(&caps.encode().unwrap()), Ok(caps));
+    let timing = [0x6e, 0x06, 0x4e, 0x00, 0x1a, 0x5e, 0x17, 0x70];
+    let timing = [&timing[..], &[checksum(0x50, &timing)]].concat();
+    assert_eq!(
+        DisplayMessage::decode(&timing),
+        Ok(DisplayMessage::TimingReply {
+            status: 0,
+            horizontal_frequency: 6750,
+            vertical_frequency: 6000,
+        })
+    );
+
+    let unexpected = encode_frame(DISPLAY_ADDRESS, HOST_VIRTUAL_ADDRESS, &[0x01, 0x10]);
+    assert_eq!(
+        DisplayMessage::decode(&unexpected),
+        Err(PacketError::UnexpectedOpcode(0x01))
+    );
+
+    for message in [
+        HostMessage::Null,
+        HostMessage::VcpRequest { feature: 0x10 },
+        HostMessage::SetVcp {
+            feature: 0x10,
+            value: 0x1234,
+        },
+        HostMessage::TimingRequest,
+        HostMessage::SaveSettings,
+        HostMessage::CapabilitiesRequest { offset: 0x1234 },
+        HostMessage::TableRead {
+            feature: 0x73,
+            offset: 0x20,
+        },
+        HostMessage::TableWrite {
+            feature: 0x73,
+            offset: 0x20,
+            data: &[1, 2, 3],
+        },
+    ] {
+        assert_eq!(HostMessage::decode(&message.encode().unwrap()), Ok(message));
+    }
+}
@@ -0,0 +1,488 @@
+//! Multi-part DDC/CI transfers, independent of any particular I/O backend.
+//!
+//! Each transfer hands out the next request to send with `request()`, and
+//! accepts the display's reply through `receive()`. Replies that are corrupt,
+//! unexpected, or for the wrong offset are retried a limited number of times
+//! before the transfer fails.
+
+use {
+    super::{
+        packet::{DisplayMessage, HostMessage, PacketError, MAX_DATA_LEN},
+        Command, FeatureCode,
+    },
+    std::{
+        error::Error,
+        fmt::{self, Display, Formatter},
+    },
+};
+
+/// How many consecutive bad replies are tolerated by default.
+pub const DEFAULT_RETRIES: usize = 3;
+
+/// The state of a transfer after receiving a reply.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Progress {
+    /// Another request needs to be sent.
+    Pending,
+    /// The transfer is finished.
+    Complete,
+}
+
+/// The reason a transfer failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TransferError {
+    /// The reply could not be decoded.
+    Packet(PacketError),
+    /// The display replied with a different message, or `None` for a null
+    /// message.
+    UnexpectedReply(Option<Command>),
+    /// The reply was for a different offset than the one requested.
+    OffsetMismatch {
+        /// The offset that was requested.
+        expected: u16,
+        /// The offset of the reply.
+        found: u16,
+    },
+    /// The data does not fit within the 16-bit offsets of the protocol.
+    TooLong,
+}
+
+impl Display for TransferError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            TransferError::Packet(ref e) => write!(f, "invalid reply: {e}"),
+            TransferError::UnexpectedReply(Some(command)) => write!(f, "unexpected reply: {command}"),
+            TransferError::UnexpectedReply(None) => f.write_str("unexpected null reply"),
+            TransferError::OffsetMismatch { expected, found } =>
+                write!(f, "reply for offset {found} while expecting {expected}"),
+            TransferError::TooLong => f.write_str("transfer exceeds the maximum offset"),
+        }
+    }
+}
+
+impl Error for TransferError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TransferError::Packet(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PacketError> for TransferError {
+    fn from(e: PacketError) -> Self {
+        TransferError::Packet(e)
+    }
+}
+
+/// Reads the capability string from a display.
+///
+/// The string is requested in fragments using `0xF3` requests, until the
+/// display replies with an empty fragment.
+#[derive(Debug, Clone)]
+pub struct CapabilitiesReader {
+    fragments: Fragments,
+}
+
+impl Default for CapabilitiesReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CapabilitiesReader {
+    /// Creates a reader that tolerates [`DEFAULT_RETRIES`] consecutive bad
+    /// replies.
+    pub fn new() -> Self {
+        Self::with_retries(DEFAULT_RETRIES)
+    }
+
+    /// Creates a reader that tolerates `retries` consecutive bad replies.
+    pub fn with_retries(retries: usize) -> Self {
+        CapabilitiesReader {
+            fragments: Fragments::new(retries),
+        }
+    }
+
+    /// The request to send next.
+    pub fn request(&self) -> HostMessage<'static> {
+        HostMessage::CapabilitiesRequest {
+            offset: self.fragments.offset,
+        }
+    }
+
+    /// Accepts a reply frame read from the display.
+    ///
+    /// Bad replies are ignored so that the same request can be sent again,
+    /// until too many have been received in a row.
+    pub fn receive(&mut self, frame: &[u8]) -> Result<Progress, TransferError> {
+        self.fragments.receive(frame, |reply| match reply {
+            DisplayMessage::CapabilitiesReply { offset, data } => Ok((offset, data, data.is_empty())),
+            reply => Err(TransferError::UnexpectedReply(reply.command())),
+        })
+    }
+
+    /// Whether the complete capability string has been received.
+    pub fn is_complete(&self) -> bool {
+        self.fragments.complete
+    }
+
+    /// The capability string received so far, without any trailing NUL
+    /// bytes.
+    pub fn capability_string(&self) -> &[u8] {
+        let data = &self.fragments.data[..];
+        let len = data.iter().rposition(|&c| c != 0).map_or(0, |end| end + 1);
+        &data[..len]
+    }
+
+    /// Consumes the reader, returning the capability string.
+    pub fn into_capability_string(self) -> Vec<u8> {
+        let len = self.capability_string().len();
+        let mut data = self.fragments.data;
+        data.truncate(len);
+        data
+    }
+}
+
+/// Reads the contents of a table VCP feature.
+///
+/// The table is requested in fragments using `0xE2` requests, until the
+/// display replies with a fragment shorter than the maximum. The result can
+/// be formatted with `mccs_db::TableInterpretation::format`.
+#[derive(Debug, Clone)]
+pub struct TableReader {
+    feature: FeatureCode,
+    fragments: Fragments,
+}
+
+impl TableReader {
+    /// Creates a reader for `feature` that tolerates [`DEFAULT_RETRIES`]
+    /// consecutive bad replies.
+    pub fn new(feature: FeatureCode) -> Self {
+        Self::with_retries(feature, DEFAULT_RETRIES)
+    }
+
+    /// Creates a reader for `feature` that tolerates `retries` consecutive
+    /// bad replies.
+    pub fn with_retries(feature: FeatureCode, retries: usize) -> Self {
+        TableReader {
+            feature,
+            fragments: Fragments::new(retries),
+        }
+    }
+
+    /// The request to send next.
+    pub fn request(&self) -> HostMessage<'static> {
+        HostMessage::TableRead {
+            feature: self.feature,
+            offset: self.fragments.offset,
+        }
+    }
+
+    /// Accepts a reply frame read from the display.
+    ///
+    /// Bad replies are ignored so that the same request can be sent again,
+    /// until too many have been received in a row.
+    pub fn receive(&mut self, frame: &[u8]) -> Result<Progress, TransferError> {
+        self.fragments.receive(frame, |reply| match reply {
+            DisplayMessage::TableReadReply { offset, data } => Ok((offset, data, data.len() < MAX_DATA_LEN)),
+            reply => Err(TransferError::UnexpectedReply(reply.command())),
+        })
+    }
+
+    /// Whether the complete table has been received.
+    pub fn is_complete(&self) -> bool {
+        self.fragments.complete
+    }
+
+    /// The table contents received so far.
+    pub fn data(&self) -> &[u8] {
+        &self.fragments.data
+    }
+
+    /// Consumes the reader, returning the table contents.
+    pub fn into_data(self) -> Vec<u8> {
+        self.fragments.data
+    }
+}
+
+/// Writes the contents of a table VCP feature.
+///
+/// The table is split into fragments of at most [`MAX_DATA_LEN`] bytes, each
+/// sent as a `0xE7` request at its offset. The display does not reply to
+/// table writes, so the caller reports whether each request was written.
+#[derive(Debug, Clone)]
+pub struct TableWriter<'a> {
+    feature: FeatureCode,
+    data: &'a [u8],
+    offset: usize,
+    retries: usize,
+    max_retries: usize,
+}
+
+impl<'a> TableWriter<'a> {
+    /// Creates a writer of `data` to `feature` that tolerates
+    /// [`DEFAULT_RETRIES`] consecutive failed writes.
+    pub fn new(feature: FeatureCode, data: &'a [u8]) -> Result<Self, TransferError> {
+        Self::with_retries(feature, data, DEFAULT_RETRIES)
+    }
+
+    /// Creates a writer of `data` to `feature` that tolerates `retries`
+    /// consecutive failed writes.
+    pub fn with_retries(feature: FeatureCode, data: &'a [u8], retries: usize) -> Result<Self, TransferError> {
+        match data.len() {
+            len if len > u16::MAX as usize => Err(TransferError::TooLong),
+            _ => Ok(TableWriter {
+                feature,
+                data,
+                offset: 0,
+                retries: 0,
+                max_retries: retries,
+            }),
+        }
+    }
+
+    /// The request to send next, or `None` once every fragment has been
+    /// written.
+    pub fn request(&self) -> Option<HostMessage<'a>> {
+        if self.is_complete() {
+            return None
+        }
+
+        let data = &self.data[self.offset..];
+        Some(HostMessage::TableWrite {
+            feature: self.feature,
+            offset: self.offset as u16,
+            data: &data[..data.len().min(MAX_DATA_LEN)],
+        })
+    }
+
+    /// Moves on to the next fragment once the current request was written.
+    pub fn written(&mut self) -> Progress {
+        self.offset = self.data.len().min(self.offset + MAX_DATA_LEN);
+        self.retries = 0;
+        match self.is_complete() {
+            true => Progress::Complete,
+            false => Progress::Pending,
+        }
+    }
+
+    /// Records that the current request failed to be written, returning
+    /// whether it should be sent again.
+    pub fn failed(&mut self) -> bool {
+        self.retries += 1;
+        self.retries <= self.max_retries
+    }
+
+    /// Whether every fragment has been written.
+    pub fn is_complete(&self) -> bool {
+        self.offset >= self.data.len()
+    }
+}
+
+/// Reassembles data received in fragments at increasing offsets.
+#[derive(Debug, Clone)]
+struct Fragments {
+    data: Vec<u8>,
+    offset: u16,
+    complete: bool,
+    retries: usize,
+    max_retries: usize,
+}
+
+impl Fragments {
+    fn new(max_retries: usize) -> Self {
+        Fragments {
+            data: Vec::new(),
+            offset: 0,
+            complete: false,
+            retries: 0,
+            max_retries,
+        }
+    }
+
+    /// Accepts a reply, using `fragment` to extract its offset, its data, and
+    /// whether it is the last one.
+    fn receive<'a, F>(&mut self, frame: &'a [u8], fragment: F) -> Result<Progress, TransferError>
+    where
+        F: FnOnce(DisplayMessage<'a>) -> Result<(u16, &'a [u8], bool), TransferError>,
+    {
+        if self.complete {
+            return Ok(Progress::Complete)
+        }
+
+        match self.fragment(frame, fragment) {
+            Ok(progress) => {
+                self.retries = 0;
+                Ok(progress)
+            },
+            Err(e @ TransferError::TooLong) => Err(e),
+            Err(e) if self.retries >= self.max_retries => Err(e),
+            Err(_) => {
+                self.retries += 1;
+                Ok(Progress::Pending)
+            },
+        }
+    }
+
+    fn fragment<'a, F>(&mut self, frame: &'a [u8], fragment: F) -> Result<Progress, TransferError>
+    where
+        F: FnOnce(DisplayMessage<'a>) -> Result<(u16, &'a [u8], bool), TransferError>,
+    {
+        let (offset, data, last) = fragment(DisplayMessage::decode(frame)?)?;
+        if offset != self.offset {
+            return Err(TransferError::OffsetMismatch {
+                expected: self.offset,
+                found: offset,
+            })
+        }
+
+        let next = u16::try_from(data.len())
+            .ok()
+            .and_then(|len| self.offset.checked_add(len))
+            .ok_or(TransferError::TooLong)?;
+        self.data.extend_from_slice(data);
+        self.offset = next;
+        self.complete = last;
+        Ok(match last {
+            true => Progress::Complete,
+            false => Progress::Pending,
+        })
+    }
+}
+
+#[cfg(test)]
+fn caps_reply(offset: u16, data: &[u8]) -> Vec<u8> {
+    DisplayMessage::CapabilitiesReply { offset, data }.encode().unwrap()
+}
+
+#[test]
+fn read_capabilities() {
+    let caps = b"(prot(monitor)type(lcd)model(U3011)cmds(01 02 03 07 0C E3 F3)vcp(02 04 05 08 10 12 14(01 05 08 0B))\0";
+    let mut reader = CapabilitiesReader::new();
+    let mut sent = Vec::new();
+    while !reader.is_complete() {
+        let request = reader.request();
+        sent.push(request);
+        let offset = match request {
+            HostMessage::CapabilitiesRequest { offset } => offset,
+            request => panic!("unexpected request {request:?}"),
+        };
+        let start = (offset as usize).min(caps.len());
+        let data = &caps[start..caps.len().min(start + 32)];
+        assert_eq!(
+            reader.receive(&caps_reply(offset, data)),
+            Ok(match data.is_empty() {
+                true => Progress::Complete,
+                false => Progress::Pending,
+            })
+        );
+    }
+    assert_eq!(sent.len(), 5);
+    assert_eq!(reader.capability_string(), &caps[..caps.len() - 1]);
+    assert_eq!(reader.into_capability_string(), &caps[..caps.len() - 1]);
+}
+
+#[test]
+fn read_capabilities_retries() {
+    let mut reader = CapabilitiesReader::with_retries(2);
+    assert_eq!(reader.receive(&caps_reply(0, b"(prot(")), Ok(Progress::Pending));
+    assert_eq!(reader.request(), HostMessage::CapabilitiesRequest { offset: 6 });
+
+    // a duplicated reply, a corrupted reply, then the right one
+    assert_eq!(reader.receive(&caps_reply(0, b"(prot(")), Ok(Progress::Pending));
+    let mut corrupted = caps_reply(6, b"monitor))");
+    corrupted[4] ^= 1;
+    assert_eq!(reader.receive(&corrupted), Ok(Progress::Pending));
+    assert_eq!(reader.request(), HostMessage::CapabilitiesRequest { offset: 6 });
+    assert_eq!(reader.receive(&caps_reply(6, b"monitor))")), Ok(Progress::Pending));
+
+    let null = DisplayMessage::Null.encode().unwrap();
+    assert_eq!(reader.receive(&null), Ok(Progress::Pending));
+    assert_eq!(reader.receive(&caps_reply(0, b"")), Ok(Progress::Pending));
+    assert_eq!(
+        reader.receive(&caps_reply(32, b"")),
+        Err(TransferError::OffsetMismatch {
+            expected: 15,
+            found: 32
+        })
+    );
+    assert!(!reader.is_complete());
+
+    // the retry count resets after each good reply
+    let mut reader = CapabilitiesReader::with_retries(1);
+    assert_eq!(reader.receive(&null), Ok(Progress::Pending));
+    assert_eq!(reader.receive(&caps_reply(0, b"(")), Ok(Progress::Pending));
+    assert_eq!(reader.receive(&null), Ok(Progress::Pending));
+    assert_eq!(reader.receive(&caps_reply(1, b")")), Ok(Progress::Pending));
+    assert_eq!(reader.receive(&caps_reply(2, b"")), Ok(Progress::Complete));
+    assert_eq!(reader.into_capability_string(), b"()");
+}
+
+#[test]
+fn read_table() {
+    let table = (0..70).collect::<Vec<u8>>();
+    let mut reader = TableReader::new(0x73);
+    let mut offsets = Vec::new();
+    while !reader.is_complete() {
+        let offset = match reader.request() {
+            HostMessage::TableRead { feature: 0x73, offset } => offset,
+            request => panic!("unexpected request {request:?}"),
+        };
+        offsets.push(offset);
+        let start = offset as usize;
+        let data = &table[start..table.len().min(start + MAX_DATA_LEN)];
+        let reply = DisplayMessage::TableReadReply { offset, data }.encode().unwrap();
+        reader.receive(&reply).unwrap();
+        // a stale duplicate of the same reply is ignored
+        if !reader.is_complete() {
+            assert_eq!(reader.receive(&reply), Ok(Progress::Pending));
+        }
+    }
+    assert_eq!(offsets, [0, 32, 64]);
+    assert_eq!(reader.into_data(), table);
+
+    let mut reader = TableReader::with_retries(0x73, 0);
+    assert_eq!(
+        reader.receive(&caps_reply(0, b"")),
+        Err(TransferError::UnexpectedReply(Some(Command::CapabilitiesReply)))
+    );
+}
+
+#[test]
+fn write_table() {
+    let table = (0..70).collect::<Vec<u8>>();
+    let mut writer = TableWriter::with_retries(0x73, &table, 1).unwrap();
+    let mut written = Vec::new();
+    while let Some(request) = writer.request() {
+        let frame = request.encode().unwrap();
+        match HostMessage::decode(&frame).unwrap() {
+            HostMessage::TableWrite {
+                feature: 0x73,
+                offset,
+                data,
+            } => {
+                assert_eq!(offset as usize, written.len());
+                written.extend_from_slice(data);
+            },
+            request => panic!("unexpected request {request:?}"),
+        }
+        writer.written();
+    }
+    assert_eq!(written, table);
+
+    let mut writer = TableWriter::with_retries(0x73, &table, 1).unwrap();
+    assert!(writer.failed());
+    assert!(!writer.failed());
+    assert_eq!(writer.written(), Progress::Pending);
+    assert!(writer.failed());
+    assert_eq!(writer.written(), Progress::Pending);
+    assert_eq!(writer.written(), Progress::Complete);
+    assert_eq!(writer.request(), None);
+
+    assert_eq!(TableWriter::new(0x73, &[]).unwrap().request(), None);
+    assert_eq!(
+        TableWriter::new(0x73, &vec![0; 0x10000]).unwrap_err(),
+        TransferError::TooLong
+    );
+}
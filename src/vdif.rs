@@ -0,0 +1,415 @@
+//! VESA Video Display Information Format decoding.
+//!
+//! A VDIF block is a packed little-endian structure describing the monitor,
+//! followed by one or more operational limits sections that each list the
+//! preadjusted timings supported within those limits. Names and serial
+//! numbers are stored in a trailing string table.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+const SIGNATURE: &[u8; 4] = b"VDIF";
+const HEADER_LEN: usize = 116;
+const LIMITS_TAG: u32 = 1;
+const TIMING_TAG: u32 = 2;
+
+/// A decoded VDIF block.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Vdif {
+    /// The VDIF structure version and revision.
+    pub version: (u16, u16),
+    /// The date the block was written, as year, month and day.
+    pub date: (u16, u16, u16),
+    /// The date the monitor was manufactured, as year, month and day.
+    pub date_manufactured: (u16, u16, u16),
+    /// The revision of this description.
+    pub file_revision: String,
+    /// The name of the manufacturer.
+    pub manufacturer: String,
+    /// The model number.
+    pub model: String,
+    /// The minimum VDIF index.
+    pub min_vdif_index: String,
+    /// The version of the model.
+    pub model_version: String,
+    /// The serial number of the monitor.
+    pub serial_number: String,
+    /// The physical characteristics of the monitor.
+    pub monitor: MonitorDescription,
+    /// The operational limits, each with its preadjusted timings.
+    pub limits: Vec<OperationalLimits>,
+    /// Whether the checksum matches the contents of the block.
+    pub checksum_valid: bool,
+}
+
+/// The physical characteristics of a monitor described by VDIF.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MonitorDescription {
+    /// `0` for monochrome and `1` for colour displays.
+    pub monitor_type: u8,
+    /// The diagonal size of the CRT, in inches.
+    pub crt_size: u8,
+    /// The red, green and blue border levels, in percent.
+    pub border: [u8; 3],
+    /// The red, green and blue phosphor decay times, in microseconds.
+    pub phosphor_decay: [u32; 3],
+    /// The white point as CIE xyY, scaled by 1000.
+    pub white_point: [u16; 3],
+    /// The red, green and blue chromaticity as CIE xy, scaled by 1000.
+    pub chromaticity: [(u16, u16); 3],
+    /// The red, green and blue gamma exponents, scaled by 1000.
+    pub gamma: [u16; 3],
+}
+
+/// A range of signals a monitor accepts.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OperationalLimits {
+    /// The maximum addressable width and height, in pixels.
+    pub max_resolution: (u16, u16),
+    /// The maximum active width and height, in millimetres.
+    pub max_active_size: (u16, u16),
+    /// The video signal level: `0` for TTL, `1` for analog and `2` for ECL.
+    pub video_type: u8,
+    /// The sync signal level, using the same values as `video_type`.
+    pub sync_type: u8,
+    /// How sync is carried, such as separately or composite.
+    pub sync_configuration: u8,
+    /// The termination resistance, in ohms.
+    pub termination_resistance: u16,
+    /// The white, black, blank and sync levels, in millivolts.
+    pub levels: [u16; 4],
+    /// The maximum pixel clock, in kHz.
+    pub max_pixel_clock: u32,
+    /// The minimum and maximum horizontal frequency, in Hz.
+    pub horizontal_frequency: (u32, u32),
+    /// The minimum and maximum vertical frequency, in mHz.
+    pub vertical_frequency: (u32, u32),
+    /// The minimum horizontal retrace time, in nanoseconds.
+    pub min_horizontal_retrace: u16,
+    /// The minimum vertical retrace time, in microseconds.
+    pub min_vertical_retrace: u16,
+    /// The timings the monitor is preadjusted for within these limits.
+    pub timings: Vec<PreadjustedTiming>,
+}
+
+/// A timing a monitor is preadjusted for.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PreadjustedTiming {
+    /// The name of the timing, usually a VESA mode number.
+    pub name: u32,
+    /// The addressable width and height, in pixels.
+    pub resolution: (u16, u16),
+    /// The addressable width and height, in millimetres.
+    pub addressable_size: (u16, u16),
+    /// The pixel aspect ratio as width to height.
+    pub pixel_ratio: (u8, u8),
+    /// `0` for non-interlaced and `1` for interlaced scanning.
+    pub scan_type: u8,
+    /// The horizontal and vertical sync polarity, `1` being positive.
+    pub sync_polarity: (u8, u8),
+    /// The character width, in pixels.
+    pub character_width: u16,
+    /// The pixel clock, in kHz.
+    pub pixel_clock: u32,
+    /// The horizontal frequency, in Hz.
+    pub horizontal_frequency: u32,
+    /// The vertical frequency, in mHz.
+    pub vertical_frequency: u32,
+    /// The total line time, in nanoseconds.
+    pub horizontal_total: u32,
+    /// The total frame time, in microseconds.
+    pub vertical_total: u32,
+    /// The addressable time, blank start, blank time, sync start and sync
+    /// time of each line, in nanoseconds.
+    pub horizontal: [u16; 5],
+    /// The addressable time, blank start, blank time, sync start and sync
+    /// time of each frame, in microseconds.
+    pub vertical: [u16; 5],
+}
+
+/// An error encountered while decoding a VDIF block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum VdifError {
+    /// The block does not start with the `VDIF` signature.
+    InvalidSignature,
+    /// The block uses a version of the format that is not understood.
+    UnsupportedVersion(u16, u16),
+    /// The block ended before the data at this offset.
+    Truncated(usize),
+    /// A section at this offset has an unexpected tag or length.
+    InvalidSection(usize),
+}
+
+impl Display for VdifError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            VdifError::InvalidSignature => f.write_str("missing VDIF signature"),
+            VdifError::UnsupportedVersion(version, revision) =>
+                write!(f, "unsupported VDIF version {version}.{revision}"),
+            VdifError::Truncated(offset) => write!(f, "VDIF truncated at byte {offset}"),
+            VdifError::InvalidSection(offset) => write!(f, "invalid VDIF section at byte {offset}"),
+        }
+    }
+}
+
+impl Error for VdifError {}
+
+/// Bounds checked little-endian reads.
+#[derive(Copy, Clone)]
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N], VdifError> {
+        offset
+            .checked_add(N)
+            .and_then(|end| self.0.get(offset..end))
+            .map(|bytes| bytes.try_into().unwrap())
+            .ok_or(VdifError::Truncated(offset))
+    }
+
+    fn u8(&self, offset: usize) -> Result<u8, VdifError> {
+        self.bytes::<1>(offset).map(|[b]| b)
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, VdifError> {
+        self.bytes(offset).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, VdifError> {
+        self.bytes(offset).map(u32::from_le_bytes)
+    }
+
+    fn offset(&self, offset: usize) -> Result<usize, VdifError> {
+        self.u32(offset).map(|o| o as usize)
+    }
+
+    fn string(&self, table: usize, offset: usize) -> Result<String, VdifError> {
+        let start = self
+            .offset(offset)?
+            .checked_add(table)
+            .ok_or(VdifError::Truncated(offset))?;
+        let string = self.0.get(start..).ok_or(VdifError::Truncated(start))?;
+        let len = string
+            .iter()
+            .position(|&c| c == 0)
+            .ok_or(VdifError::Truncated(self.0.len()))?;
+        Ok(String::from_utf8_lossy(&string[..len]).into())
+    }
+
+    /// Reads the number of sections at `offset`, each at least `min_len` bytes
+    /// long, rejecting counts that could not fit in the block.
+    fn count(&self, offset: usize, min_len: usize) -> Result<usize, VdifError> {
+        match self.offset(offset)? {
+            count if count > self.0.len() / min_len => Err(VdifError::Truncated(self.0.len())),
+            count => Ok(count),
+        }
+    }
+
+    /// Checks the header of the section at `offset`, returning its length.
+    fn section(&self, offset: usize, tag: u32, min_len: usize) -> Result<usize, VdifError> {
+        let len = self.offset(offset)?;
+        match self.u32(offset + 4)? == tag && len >= min_len {
+            true => self.bytes::<0>(offset + len).map(|_| len),
+            false => Err(VdifError::InvalidSection(offset)),
+        }
+    }
+}
+
+impl Vdif {
+    /// Decodes a VDIF block.
+    pub fn parse(data: &[u8]) -> Result<Self, VdifError> {
+        let r = Reader(data);
+        if r.bytes(0)? != *SIGNATURE {
+            return Err(VdifError::InvalidSignature)
+        }
+        let version = (r.u16(12)?, r.u16(14)?);
+        if version.0 != 1 {
+            return Err(VdifError::UnsupportedVersion(version.0, version.1))
+        }
+        r.bytes::<HEADER_LEN>(0)?;
+
+        let len = r.offset(4)?;
+        let file = data.get(..len).ok_or(VdifError::Truncated(data.len()))?;
+        let checksum = file
+            .get(12..)
+            .unwrap_or_default()
+            .iter()
+            .map(|&b| b as u32)
+            .fold(0, u32::wrapping_add);
+
+        let date = |offset| -> Result<_, VdifError> { Ok((r.u16(offset)?, r.u16(offset + 2)?, r.u16(offset + 4)?)) };
+        let strings = r.offset(112)?;
+        let monitor = MonitorDescription {
+            monitor_type: r.u8(52)?,
+            crt_size: r.u8(53)?,
+            border: [r.u8(54)?, r.u8(55)?, r.u8(56)?],
+            phosphor_decay: [r.u32(60)?, r.u32(64)?, r.u32(68)?],
+            white_point: [r.u16(72)?, r.u16(74)?, r.u16(76)?],
+            chromaticity: [
+                (r.u16(78)?, r.u16(80)?),
+                (r.u16(82)?, r.u16(84)?),
+                (r.u16(86)?, r.u16(88)?),
+            ],
+            gamma: [r.u16(90)?, r.u16(92)?, r.u16(94)?],
+        };
+
+        let mut limits = Vec::new();
+        let mut offset = r.offset(100)?;
+        for index in 0..r.count(96, 64)? {
+            if index > 0 {
+                // each section must advance, or a hostile count would loop forever
+                offset = match r.offset(offset + 60)? {
+                    0 => None,
+                    next => offset.checked_add(next),
+                }
+                .ok_or(VdifError::InvalidSection(offset))?;
+            }
+            limits.push(operational_limits(r, offset)?);
+        }
+
+        Ok(Vdif {
+            version,
+            date: date(16)?,
+            date_manufactured: date(22)?,
+            file_revision: r.string(strings, 28)?,
+            manufacturer: r.string(strings, 32)?,
+            model: r.string(strings, 36)?,
+            min_vdif_index: r.string(strings, 40)?,
+            model_version: r.string(strings, 44)?,
+            serial_number: r.string(strings, 48)?,
+            monitor,
+            limits,
+            checksum_valid: checksum == r.u32(8)?,
+        })
+    }
+}
+
+fn operational_limits(r: Reader, offset: usize) -> Result<OperationalLimits, VdifError> {
+    let len = r.section(offset, LIMITS_TAG, 64)?;
+    let at = |field| offset + field;
+
+    let mut timing = offset + len;
+    let mut timings = Vec::new();
+    for _ in 0..r.count(at(56), 68)? {
+        timings.push(preadjusted_timing(r, timing)?);
+        timing += r.offset(timing)?;
+    }
+
+    Ok(OperationalLimits {
+        max_resolution: (r.u16(at(8))?, r.u16(at(10))?),
+        max_active_size: (r.u16(at(12))?, r.u16(at(14))?),
+        video_type: r.u8(at(16))?,
+        sync_type: r.u8(at(17))?,
+        sync_configuration: r.u8(at(18))?,
+        termination_resistance: r.u16(at(22))?,
+        levels: [r.u16(at(24))?, r.u16(at(26))?, r.u16(at(28))?, r.u16(at(30))?],
+        max_pixel_clock: r.u32(at(32))?,
+        horizontal_frequency: (r.u32(at(36))?, r.u32(at(40))?),
+        vertical_frequency: (r.u32(at(44))?, r.u32(at(48))?),
+        min_horizontal_retrace: r.u16(at(52))?,
+        min_vertical_retrace: r.u16(at(54))?,
+        timings,
+    })
+}
+
+fn preadjusted_timing(r: Reader, offset: usize) -> Result<PreadjustedTiming, VdifError> {
+    r.section(offset, TIMING_TAG, 68)?;
+    let at = |field| offset + field;
+    let times = |field| -> Result<[u16; 5], VdifError> {
+        Ok([
+            r.u16(at(field))?,
+            r.u16(at(field + 2))?,
+            r.u16(at(field + 4))?,
+            r.u16(at(field + 6))?,
+            r.u16(at(field + 8))?,
+        ])
+    };
+
+    Ok(PreadjustedTiming {
+        name: r.u32(at(8))?,
+        resolution: (r.u16(at(12))?, r.u16(at(14))?),
+        addressable_size: (r.u16(at(16))?, r.u16(at(18))?),
+        pixel_ratio: (r.u8(at(20))?, r.u8(at(21))?),
+        scan_type: r.u8(at(23))?,
+        sync_polarity: (r.u8(at(24))?, r.u8(at(25))?),
+        character_width: r.u16(at(26))?,
+        pixel_clock: r.u32(at(28))?,
+        horizontal_frequency: r.u32(at(32))?,
+        vertical_frequency: r.u32(at(36))?,
+        horizontal_total: r.u32(at(40))?,
+        vertical_total: r.u32(at(44))?,
+        horizontal: times(48)?,
+        vertical: times(58)?,
+    })
+}
+
+#[test]
+fn decode_vdif() {
+    fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    let (limits, timing, strings) = (HEADER_LEN, HEADER_LEN + 64, HEADER_LEN + 64 + 68);
+    let table = b"A\0ACME\0CRT-17\0\0B\x000042\0";
+    let mut data = vec![0u8; strings + table.len()];
+    put(&mut data, 0, SIGNATURE);
+    let len = data.len() as u32;
+    put(&mut data, 4, &len.to_le_bytes());
+    put(&mut data, 12, &[1, 0, 0, 0]);
+    put(&mut data, 16, &[0xc9, 0x07, 6, 0, 15, 0]);
+    for (field, string) in [0u32, 2, 7, 14, 15, 17].into_iter().enumerate() {
+        put(&mut data, 28 + field * 4, &string.to_le_bytes());
+    }
+    put(&mut data, 52, &[1, 17]);
+    put(&mut data, 90, &[0xc4, 0x09, 0xc4, 0x09, 0xc4, 0x09]);
+    put(&mut data, 96, &1u32.to_le_bytes());
+    put(&mut data, 100, &(limits as u32).to_le_bytes());
+    put(&mut data, 112, &(strings as u32).to_le_bytes());
+    put(&mut data, limits, &[64, 0, 0, 0, 1, 0, 0, 0, 0x00, 0x04, 0x00, 0x03]);
+    put(&mut data, limits + 32, &135_000u32.to_le_bytes());
+    put(&mut data, limits + 56, &[1, 0, 0, 0, 132, 0, 0, 0]);
+    put(&mut data, timing, &[
+        68, 0, 0, 0, 2, 0, 0, 0, 0x05, 0x01, 0, 0, 0x00, 0x04, 0x00, 0x03,
+    ]);
+    put(&mut data, timing + 25, &[1]);
+    put(&mut data, timing + 28, &65_000u32.to_le_bytes());
+    put(&mut data, strings, table);
+    let checksum = data[12..].iter().map(|&b| b as u32).sum::<u32>();
+    put(&mut data, 8, &checksum.to_le_bytes());
+
+    let vdif = Vdif::parse(&data).unwrap();
+    let mut hostile = data.clone();
+    put(&mut hostile, 96, &u32::MAX.to_le_bytes());
+    assert_eq!(Vdif::parse(&hostile), Err(VdifError::Truncated(data.len())));
+    put(&mut hostile, 96, &2u32.to_le_bytes());
+    put(&mut hostile, limits + 60, &0u32.to_le_bytes());
+    assert_eq!(Vdif::parse(&hostile), Err(VdifError::InvalidSection(limits)));
+
+    assert_eq!(vdif.version, (1, 0));
+    assert_eq!(vdif.date, (1993, 6, 15));
+    assert_eq!(
+        (&vdif.manufacturer[..], &vdif.model[..], &vdif.serial_number[..]),
+        ("ACME", "CRT-17", "0042")
+    );
+    assert_eq!((vdif.monitor.monitor_type, vdif.monitor.crt_size), (1, 17));
+    assert_eq!(vdif.monitor.gamma, [2500; 3]);
+    assert!(vdif.checksum_valid);
+
+    let limits = &vdif.limits[0];
+    assert_eq!(limits.max_resolution, (1024, 768));
+    assert_eq!(limits.max_pixel_clock, 135_000);
+    assert_eq!(limits.timings.len(), 1);
+    assert_eq!(limits.timings[0].name, 0x105);
+    assert_eq!(limits.timings[0].resolution, (1024, 768));
+    assert_eq!(limits.timings[0].sync_polarity, (0, 1));
+    assert_eq!(limits.timings[0].pixel_clock, 65_000);
+
+    assert_eq!(Vdif::parse(&data[..100]), Err(VdifError::Truncated(0)));
+    assert_eq!(Vdif::parse(&data[..strings]), Err(VdifError::Truncated(strings)));
+    data[12] = 2;
+    assert_eq!(Vdif::parse(&data), Err(VdifError::UnsupportedVersion(2, 0)));
+    data[0] = b'X';
+    assert_eq!(Vdif::parse(&data), Err(VdifError::InvalidSignature));
+}